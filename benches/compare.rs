@@ -0,0 +1,59 @@
+// Benchmarks of the diff engine on generated documents, so that they run offline. Run them with
+// `cargo bench`, or e.g. `cargo bench -- wide` for a single group.
+
+mod generators;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use jsondiff::{compare_with, CompareOptions, Patch};
+use std::hint::black_box;
+
+fn bench(c: &mut Criterion, group: &str, (left, right): (serde_json::Value, serde_json::Value)) {
+    let mut group = c.benchmark_group(group);
+    for (name, parallel) in [("sequential", false), ("parallel", true)] {
+        let options = CompareOptions {
+            parallel,
+            ..Default::default()
+        };
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut patches: Vec<Patch> = Vec::new();
+                compare_with(black_box(&left), black_box(&right), &mut patches, &options);
+                patches
+            })
+        });
+    }
+    group.finish();
+}
+
+fn wide_objects(c: &mut Criterion) {
+    bench(c, "wide objects", generators::wide_object(100_000));
+}
+
+fn deep_nesting(c: &mut Criterion) {
+    bench(c, "deep nesting", generators::deep_nesting(100));
+}
+
+fn long_arrays_with_insertions(c: &mut Criterion) {
+    bench(
+        c,
+        "long arrays with insertions",
+        generators::long_array_with_insertions(20_000, 10),
+    );
+}
+
+fn large_near_identical(c: &mut Criterion) {
+    bench(
+        c,
+        "large near-identical documents",
+        generators::near_identical(20_000),
+    );
+}
+
+criterion_group!(
+    benches,
+    wide_objects,
+    deep_nesting,
+    long_arrays_with_insertions,
+    large_near_identical
+);
+criterion_main!(benches);
@@ -0,0 +1,116 @@
+// Generators for the benchmark documents, so that the benchmarks run offline and on the same
+// input every time. Each returns a pair of documents to compare.
+
+// A small linear congruential generator, seeded the same way on every run.
+pub struct Random(u64);
+
+impl Random {
+    pub fn new() -> Random {
+        Random(42)
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+}
+
+// A record like the ones in an inventory dump: nested objects, arrays, strings and decimals.
+pub fn record(random: &mut Random, id: usize) -> serde_json::Value {
+    let tags = &["new", "sale", "clearance"][..random.next() as usize % 4];
+    serde_json::json!({
+        "id": id,
+        "sku": format!("SKU-{:08}", random.next() % 100_000_000),
+        "name": format!("Item {}", id),
+        "price": serde_json::Number::from_string_unchecked(format!(
+            "{}.{:02}",
+            random.next() % 1000,
+            random.next() % 100
+        )),
+        "tags": tags,
+        "warehouse": {
+            "id": random.next() % 50,
+            "location": {"aisle": random.next() % 20, "shelf": random.next() % 10},
+        },
+        "active": random.next().is_multiple_of(2),
+    })
+}
+
+pub fn inventory(random: &mut Random, records: usize) -> serde_json::Value {
+    let records: Vec<serde_json::Value> = (0..records).map(|id| record(random, id)).collect();
+    serde_json::json!({"version": 1, "items": records})
+}
+
+// An inventory dump with one in a thousand records changed.
+pub fn near_identical(records: usize) -> (serde_json::Value, serde_json::Value) {
+    let left = inventory(&mut Random::new(), records);
+    let mut right = left.clone();
+    for i in (0..records).step_by(1000) {
+        right["items"][i]["warehouse"]["location"]["shelf"] = serde_json::json!(99);
+    }
+    (left, right)
+}
+
+// An object with many keys, of which one in a hundred is changed, removed or added.
+pub fn wide_object(keys: usize) -> (serde_json::Value, serde_json::Value) {
+    let mut random = Random::new();
+    let left: serde_json::Map<String, serde_json::Value> = (0..keys)
+        .map(|i| {
+            (
+                format!("key-{:06}", i),
+                serde_json::json!(random.next() % 1000),
+            )
+        })
+        .collect();
+    let mut right = left.clone();
+    for i in (0..keys).step_by(100) {
+        match random.next() % 3 {
+            0 => right.insert(format!("key-{:06}", i), serde_json::json!("changed")),
+            1 => right.remove(&format!("key-{:06}", i)),
+            _ => right.insert(format!("added-{:06}", i), serde_json::json!(i)),
+        };
+    }
+    (
+        serde_json::Value::Object(left),
+        serde_json::Value::Object(right),
+    )
+}
+
+// Objects nested `levels` deep, each with a payload before the next level, and a change at the
+// bottom. Every level repeats the comparison of everything below it unless identical subtrees
+// can be recognized without walking them.
+pub fn deep_nesting(levels: usize) -> (serde_json::Value, serde_json::Value) {
+    let mut random = Random::new();
+    let mut left = serde_json::json!({"leaf": 1});
+    for _ in 0..levels {
+        left = serde_json::json!({"payload": inventory(&mut random, 50), "next": left});
+    }
+    let mut right = left.clone();
+    let mut leaf = &mut right;
+    while leaf.get("next").is_some() {
+        leaf = &mut leaf["next"];
+    }
+    leaf["leaf"] = serde_json::json!(2);
+    (left, right)
+}
+
+// A long array of records with a few records inserted, which shifts every record after them.
+pub fn long_array_with_insertions(
+    records: usize,
+    insertions: usize,
+) -> (serde_json::Value, serde_json::Value) {
+    let mut random = Random::new();
+    let left: Vec<serde_json::Value> = (0..records).map(|id| record(&mut random, id)).collect();
+    let mut right = left.clone();
+    for i in 0..insertions {
+        let index = (random.next() as usize) % right.len();
+        right.insert(index, record(&mut random, records + i));
+    }
+    (
+        serde_json::Value::Array(left),
+        serde_json::Value::Array(right),
+    )
+}
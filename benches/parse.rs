@@ -0,0 +1,27 @@
+// Benchmarks of reading a document, against serde_json parsing the same text into a Value. Run
+// them with `cargo bench --bench parse`.
+
+// Only some of the generators are used here.
+#[allow(dead_code)]
+mod generators;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use jsondiff::parser;
+use std::hint::black_box;
+
+fn parse(c: &mut Criterion) {
+    let text = generators::inventory(&mut generators::Random::new(), 20_000).to_string();
+    let mut group = c.benchmark_group("parse");
+    group.bench_function("serde_json", |b| {
+        b.iter(|| serde_json::from_slice::<serde_json::Value>(black_box(text.as_bytes())).unwrap())
+    });
+    for (name, track_locations) in [("default", false), ("locations", true)] {
+        group.bench_function(name, |b| {
+            b.iter(|| parser::parse(black_box(text.as_bytes()), track_locations).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
@@ -0,0 +1,171 @@
+// Applies patches to a document. Operations are applied in sequence as RFC 6902 specifies, so
+// array indices refer to the array as it is when each operation is applied, and "-" appends to
+// an array. Like everywhere else, "/" is the whole document.
+
+use crate::{JsonPatchOp, Patch};
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub struct Error {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.message, self.path)
+    }
+}
+
+pub fn apply(document: &mut serde_json::Value, patches: &[Patch]) -> Result<(), Error> {
+    for patch in patches {
+        apply_one(document, patch)?;
+    }
+    Ok(())
+}
+
+pub fn apply_one(document: &mut serde_json::Value, patch: &Patch) -> Result<(), Error> {
+    let error = |message: &str| Error {
+        path: patch.path.clone(),
+        message: message.to_string(),
+    };
+    if !crate::path::is_pointer(&patch.path) {
+        return Err(error("invalid JSON Pointer"));
+    }
+    let mut tokens = crate::path::tokens(&patch.path);
+    let last = match tokens.pop() {
+        Some(last) => last,
+        None => {
+            return match patch.op {
+                JsonPatchOp::Add | JsonPatchOp::Replace => {
+                    *document = patch.value.clone().ok_or_else(|| error("missing value"))?;
+                    Ok(())
+                }
+                JsonPatchOp::Remove => Err(error("cannot remove the whole document")),
+                JsonPatchOp::Reorder => Ok(()),
+            };
+        }
+    };
+    let mut parent = document;
+    for token in &tokens {
+        parent = match parent {
+            serde_json::Value::Object(map) => map.get_mut(token),
+            serde_json::Value::Array(vec) => index(token).and_then(|i| vec.get_mut(i)),
+            _ => None,
+        }
+        .ok_or_else(|| error("path not found"))?;
+    }
+    let value = || patch.value.clone().ok_or_else(|| error("missing value"));
+    match (&patch.op, parent) {
+        // Not part of RFC 6902, and object keys are unordered anyway.
+        (JsonPatchOp::Reorder, _) => Ok(()),
+        (JsonPatchOp::Add, serde_json::Value::Object(map)) => {
+            map.insert(last, value()?);
+            Ok(())
+        }
+        (JsonPatchOp::Add, serde_json::Value::Array(vec)) => {
+            let i = if last == "-" {
+                vec.len()
+            } else {
+                index(&last)
+                    .filter(|i| *i <= vec.len())
+                    .ok_or_else(|| error("index out of range"))?
+            };
+            vec.insert(i, value()?);
+            Ok(())
+        }
+        (JsonPatchOp::Remove, serde_json::Value::Object(map)) => map
+            .shift_remove(&last)
+            .map(|_| ())
+            .ok_or_else(|| error("path not found")),
+        (JsonPatchOp::Remove, serde_json::Value::Array(vec)) => {
+            let i = index(&last)
+                .filter(|i| *i < vec.len())
+                .ok_or_else(|| error("index out of range"))?;
+            vec.remove(i);
+            Ok(())
+        }
+        (JsonPatchOp::Replace, serde_json::Value::Object(map)) => {
+            let target = map.get_mut(&last).ok_or_else(|| error("path not found"))?;
+            *target = value()?;
+            Ok(())
+        }
+        (JsonPatchOp::Replace, serde_json::Value::Array(vec)) => {
+            let target = index(&last)
+                .and_then(|i| vec.get_mut(i))
+                .ok_or_else(|| error("index out of range"))?;
+            *target = value()?;
+            Ok(())
+        }
+        _ => Err(error("parent is not an object or array")),
+    }
+}
+
+// RFC 6901 array indices have no leading zeros (except 0 itself).
+pub(crate) fn index(token: &str) -> Option<usize> {
+    if token.len() > 1 && token.starts_with('0') {
+        return None;
+    }
+    token.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::apply::{apply, Error};
+    use crate::{compare, JsonPatchOp, Patch};
+
+    #[test]
+    fn test_apply_round_trip() {
+        let cases = [
+            (
+                serde_json::json!(["a", "b", "c", "d"]),
+                serde_json::json!(["a", "x"]),
+            ),
+            (
+                serde_json::json!({"a": [1, {"b": 2}], "c": 3, "d/e": {"~": 4}}),
+                serde_json::json!({"a": [1, {"b": 5}, 6], "d/e": {"~": 7}, "f": null}),
+            ),
+            (serde_json::json!({"a": 1}), serde_json::json!([1])),
+        ];
+        for (left, right) in cases {
+            let mut patches: Vec<Patch> = Vec::new();
+            compare(&left, &right, &mut patches);
+            let mut document = left.clone();
+            apply(&mut document, &patches).unwrap();
+            assert_eq!(right, document);
+        }
+    }
+
+    #[test]
+    fn test_apply_errors() {
+        let patch = |op, path: &str| Patch {
+            op,
+            path: path.to_string(),
+            value: Some(serde_json::json!(1)),
+            old_value: None,
+        };
+        let mut document = serde_json::json!({"a": [1, 2]});
+        let cases = [
+            (patch(JsonPatchOp::Add, "/a/3"), "index out of range"),
+            (patch(JsonPatchOp::Remove, "/b"), "path not found"),
+            (patch(JsonPatchOp::Replace, "/b/c"), "path not found"),
+            (
+                patch(JsonPatchOp::Add, "/a/0/b"),
+                "parent is not an object or array",
+            ),
+            (
+                patch(JsonPatchOp::Replace, "/a/~j/0"),
+                "invalid JSON Pointer",
+            ),
+        ];
+        for (patch, message) in cases {
+            let expected = Error {
+                path: patch.path.clone(),
+                message: message.to_string(),
+            };
+            assert_eq!(Err(expected), apply(&mut document, &[patch]));
+        }
+        apply(&mut document, &[patch(JsonPatchOp::Add, "/a/-")]).unwrap();
+        assert_eq!(serde_json::json!({"a": [1, 2, 1]}), document);
+    }
+}
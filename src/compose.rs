@@ -0,0 +1,319 @@
+// Composes a sequence of patches into a single patch with the same effect. Each operation is
+// folded into an earlier one on the same value or a value above it, e.g. an add followed by a
+// replace becomes a single add, and an add of a new value followed by a remove cancels out. An
+// operation can only be folded past the operations in between if it is independent of them:
+// adding or removing an array element shifts the indices after it, so without the document,
+// nothing else in that array is moved past it.
+//
+// Old values are kept where the patches have them, so the result can still be inverted.
+
+use crate::apply::apply_one;
+use crate::invert::invert;
+use crate::{JsonPatchOp, Patch};
+
+pub fn compose(patches: Vec<Patch>) -> Vec<Patch> {
+    let mut ret: Vec<Patch> = Vec::new();
+    for patch in patches {
+        fold(&mut ret, patch);
+    }
+    ret
+}
+
+fn fold(ret: &mut Vec<Patch>, mut patch: Patch) {
+    let mut j = ret.len();
+    while j > 0 {
+        j -= 1;
+        let earlier = &mut ret[j];
+        if earlier.path == patch.path {
+            match combine(earlier, &patch) {
+                Some(Some(combined)) => ret[j] = combined,
+                Some(None) => {
+                    ret.remove(j);
+                }
+                None => break,
+            }
+            return;
+        }
+        if let Some(relative) = below(&patch.path, &earlier.path) {
+            // A change inside a value that was added or replaced changes that value instead.
+            if let (JsonPatchOp::Add | JsonPatchOp::Replace, Some(value)) =
+                (&earlier.op, &mut earlier.value)
+            {
+                let mut changed = value.clone();
+                let relative = Patch {
+                    op: patch.op,
+                    path: relative.to_string(),
+                    value: patch.value.clone(),
+                    old_value: None,
+                };
+                if apply_one(&mut changed, &relative).is_ok() {
+                    *value = changed;
+                    return;
+                }
+            }
+            break;
+        }
+        if let Some(relative) = below(&earlier.path, &patch.path) {
+            // A change inside a value that is then removed or replaced is lost anyway.
+            if patch.op == JsonPatchOp::Add {
+                break;
+            }
+            let relative = relative.to_string();
+            let earlier = ret.remove(j);
+            // The old value is from before the earlier change.
+            patch.old_value = patch.old_value.take().and_then(|mut old_value| {
+                let undo = invert(&[Patch {
+                    path: relative,
+                    ..earlier
+                }])
+                .ok()?;
+                apply_one(&mut old_value, &undo[0]).ok()?;
+                Some(old_value)
+            });
+            continue;
+        }
+        if !independent(earlier, &patch) {
+            break;
+        }
+    }
+    ret.push(patch);
+}
+
+// Some(None) where the operations cancel out, and None where they can't be combined.
+fn combine(earlier: &Patch, later: &Patch) -> Option<Option<Patch>> {
+    let patch = |op, value: &Option<serde_json::Value>, old_value: &Option<serde_json::Value>| {
+        Some(Some(Patch {
+            op,
+            path: later.path.clone(),
+            value: value.clone(),
+            old_value: old_value.clone(),
+        }))
+    };
+    let replace = || {
+        if earlier.old_value.is_some() && earlier.old_value == later.value {
+            // Changed back.
+            return Some(None);
+        }
+        patch(JsonPatchOp::Replace, &later.value, &earlier.old_value)
+    };
+    // Adding at an index of an array inserts rather than replaces.
+    let index = is_index(last(&later.path));
+    match (earlier.op, later.op) {
+        (JsonPatchOp::Add, JsonPatchOp::Replace) => {
+            patch(JsonPatchOp::Add, &later.value, &earlier.old_value)
+        }
+        (JsonPatchOp::Add, JsonPatchOp::Add) if !index => {
+            patch(JsonPatchOp::Add, &later.value, &earlier.old_value)
+        }
+        // Unless the add replaced an existing member, which is then removed.
+        (JsonPatchOp::Add, JsonPatchOp::Remove) if index || earlier.old_value.is_none() => {
+            Some(None)
+        }
+        (JsonPatchOp::Add, JsonPatchOp::Remove) => {
+            patch(JsonPatchOp::Remove, &None, &earlier.old_value)
+        }
+        (JsonPatchOp::Replace, JsonPatchOp::Replace) | (JsonPatchOp::Remove, JsonPatchOp::Add) => {
+            replace()
+        }
+        (JsonPatchOp::Replace, JsonPatchOp::Add) if !index => replace(),
+        (JsonPatchOp::Replace, JsonPatchOp::Remove) => {
+            patch(JsonPatchOp::Remove, &None, &earlier.old_value)
+        }
+        _ => None,
+    }
+}
+
+// Whether two operations on unrelated paths can be swapped.
+fn independent(a: &Patch, b: &Patch) -> bool {
+    let shifts = |patch: &Patch, other: &Patch| {
+        matches!(patch.op, JsonPatchOp::Add | JsonPatchOp::Remove)
+            && is_index(last(&patch.path))
+            && below(&other.path, parent(&patch.path)).is_some()
+    };
+    !shifts(a, b) && !shifts(b, a)
+}
+
+// The path of `path` relative to `ancestor`, if it is below it.
+fn below<'a>(path: &'a str, ancestor: &str) -> Option<&'a str> {
+    if ancestor == "/" || ancestor.is_empty() {
+        return Some(path).filter(|path| *path != "/" && !path.is_empty());
+    }
+    path.strip_prefix(ancestor)
+        .filter(|relative| relative.starts_with('/'))
+}
+
+fn parent(path: &str) -> &str {
+    path.rfind('/').map_or("", |i| &path[..i])
+}
+
+fn last(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or("")
+}
+
+fn is_index(token: &str) -> bool {
+    token == "-" || (!token.is_empty() && token.bytes().all(|b| b.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::apply::apply;
+    use crate::compose::compose;
+    use crate::invert::invert;
+    use crate::{compare, parse_json_patch, Patch};
+
+    #[test]
+    fn test_compose() {
+        let document = serde_json::json!({"a": 1, "l": [1, 2, 3], "x": {"y": 0}});
+        let cases = [
+            // Add, then replace.
+            (
+                serde_json::json!([
+                    {"op": "add", "path": "/b", "value": 1},
+                    {"op": "replace", "path": "/b", "value": 2}
+                ]),
+                1,
+            ),
+            // Add, then remove.
+            (
+                serde_json::json!([
+                    {"op": "add", "path": "/b", "value": 1},
+                    {"op": "remove", "path": "/b"}
+                ]),
+                0,
+            ),
+            // Add over an existing member, then remove.
+            (
+                serde_json::json!([
+                    {"op": "add", "path": "/a", "value": 5, "old_value": 1},
+                    {"op": "remove", "path": "/a"}
+                ]),
+                1,
+            ),
+            // Add, then change inside.
+            (
+                serde_json::json!([
+                    {"op": "add", "path": "/b", "value": {"c": 1}},
+                    {"op": "replace", "path": "/b/c", "value": 2},
+                    {"op": "add", "path": "/b/d", "value": 3}
+                ]),
+                1,
+            ),
+            // Replace, then remove.
+            (
+                serde_json::json!([
+                    {"op": "replace", "path": "/a", "value": 2},
+                    {"op": "remove", "path": "/a"}
+                ]),
+                1,
+            ),
+            // Remove, then add.
+            (
+                serde_json::json!([
+                    {"op": "remove", "path": "/a"},
+                    {"op": "add", "path": "/a", "value": 3}
+                ]),
+                1,
+            ),
+            // Change inside, then replace.
+            (
+                serde_json::json!([
+                    {"op": "replace", "path": "/x/y", "value": 1},
+                    {"op": "add", "path": "/x/z", "value": 2},
+                    {"op": "replace", "path": "/x", "value": 5}
+                ]),
+                1,
+            ),
+            // Insert, then replace the inserted element.
+            (
+                serde_json::json!([
+                    {"op": "add", "path": "/l/1", "value": 5},
+                    {"op": "replace", "path": "/l/1", "value": 6}
+                ]),
+                1,
+            ),
+            // Insert and remove around an independent change.
+            (
+                serde_json::json!([
+                    {"op": "add", "path": "/l/0", "value": 0},
+                    {"op": "replace", "path": "/a", "value": 2},
+                    {"op": "remove", "path": "/l/0"}
+                ]),
+                1,
+            ),
+            // Removing an element shifts the next one into its place.
+            (
+                serde_json::json!([
+                    {"op": "remove", "path": "/l/0"},
+                    {"op": "replace", "path": "/l/0", "value": 9}
+                ]),
+                2,
+            ),
+            // Two insertions at the same index.
+            (
+                serde_json::json!([
+                    {"op": "add", "path": "/l/1", "value": 7},
+                    {"op": "add", "path": "/l/1", "value": 8}
+                ]),
+                2,
+            ),
+        ];
+        for (json_patch, expected) in cases {
+            let patches = parse_json_patch(&json_patch).unwrap();
+            let mut sequential = document.clone();
+            apply(&mut sequential, &patches).unwrap();
+            let composed = compose(patches);
+            let mut applied = document.clone();
+            apply(&mut applied, &composed).unwrap();
+            assert_eq!(sequential, applied, "{}", json_patch);
+            assert_eq!(expected, composed.len(), "{}", json_patch);
+        }
+    }
+
+    #[test]
+    fn test_compose_old_values() {
+        let document = serde_json::json!({"a": 1, "b": 2});
+        let json_patch = serde_json::json!([
+            {"op": "add", "path": "/a", "value": 5, "old_value": 1},
+            {"op": "replace", "path": "/a", "value": 6, "old_value": 5},
+            {"op": "add", "path": "/b", "value": 3, "old_value": 2},
+            {"op": "add", "path": "/b", "value": 4, "old_value": 3},
+            {"op": "add", "path": "/c", "value": 5},
+            {"op": "remove", "path": "/c"}
+        ]);
+        let composed = compose(parse_json_patch(&json_patch).unwrap());
+        let expected = parse_json_patch(&serde_json::json!([
+            {"op": "add", "path": "/a", "value": 6, "old_value": 1},
+            {"op": "add", "path": "/b", "value": 4, "old_value": 2}
+        ]))
+        .unwrap();
+        assert_eq!(expected, composed);
+        let mut applied = document.clone();
+        apply(&mut applied, &composed).unwrap();
+        apply(&mut applied, &invert(&composed).unwrap()).unwrap();
+        assert_eq!(document, applied);
+    }
+
+    #[test]
+    fn test_compose_history() {
+        let history = [
+            serde_json::json!({"a": 1, "b": {"c": [1, 2, 3], "d": "x"}, "e": [1]}),
+            serde_json::json!({"a": 2, "b": {"c": [1, 2], "d": "y"}, "e": [1, 2]}),
+            serde_json::json!({"a": 1, "b": {"c": [0], "f": true}, "e": [1, 2, 3]}),
+            serde_json::json!({"b": {"c": [0, 1], "f": false}, "e": [1, 2, 3], "g": null}),
+        ];
+        let mut patches: Vec<Patch> = Vec::new();
+        for pair in history.windows(2) {
+            compare(&pair[0], &pair[1], &mut patches);
+        }
+        let len = patches.len();
+        let composed = compose(patches);
+        assert!(composed.len() < len);
+        let (first, last) = (&history[0], &history[history.len() - 1]);
+        let mut document = first.clone();
+        apply(&mut document, &composed).unwrap();
+        assert_eq!(*last, document);
+        // The old values are those before the first patch.
+        apply(&mut document, &invert(&composed).unwrap()).unwrap();
+        assert_eq!(*first, document);
+    }
+}
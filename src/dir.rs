@@ -0,0 +1,158 @@
+// Pairs up the files in two directory trees by their path relative to the root of each tree,
+// so that e.g. snapshot directories from two releases can be compared file by file.
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Globs are matched against paths relative to the root of each tree. A file is compared if it
+// matches any include glob (or there are none) and no exclude glob.
+pub struct Filter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl Filter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Filter, globset::Error> {
+        Ok(Filter {
+            include: if include.is_empty() {
+                None
+            } else {
+                Some(build_glob_set(include)?)
+            },
+            exclude: build_glob_set(exclude)?,
+        })
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        self.include
+            .as_ref()
+            .is_none_or(|include| include.is_match(path))
+            && !self.exclude.is_match(path)
+    }
+}
+
+fn build_glob_set(globs: &[String]) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(Glob::new(glob)?);
+    }
+    builder.build()
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Pairs {
+    pub both: Vec<PathBuf>,
+    pub left_only: Vec<PathBuf>,
+    pub right_only: Vec<PathBuf>,
+}
+
+pub fn pair(left: &Path, right: &Path, filter: &Filter) -> io::Result<Pairs> {
+    let left_files = files(left, filter)?;
+    let right_files = files(right, filter)?;
+    Ok(Pairs {
+        both: left_files.intersection(&right_files).cloned().collect(),
+        left_only: left_files.difference(&right_files).cloned().collect(),
+        right_only: right_files.difference(&left_files).cloned().collect(),
+    })
+}
+
+// Symbolic links to directories are skipped rather than followed, which could loop forever.
+// Symbolic links to files are compared like files.
+fn files(root: &Path, filter: &Filter) -> io::Result<BTreeSet<PathBuf>> {
+    let mut files = BTreeSet::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+            } else if file_type.is_symlink() && entry.path().is_dir() {
+                continue;
+            } else {
+                let path = entry.path();
+                let relative = path.strip_prefix(root).unwrap();
+                if filter.matches(relative) {
+                    files.insert(relative.to_path_buf());
+                }
+            }
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dir::{pair, Filter, Pairs};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn create(root: &Path, files: &[&str]) {
+        for file in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "{}").unwrap();
+        }
+    }
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn test_pair() {
+        let left = tempfile::tempdir().unwrap();
+        let right = tempfile::tempdir().unwrap();
+        create(left.path(), &["a.json", "b/c.json", "b/d.json", "e.json"]);
+        create(right.path(), &["a.json", "b/c.json", "b/f.json"]);
+        let filter = Filter::new(&[], &[]).unwrap();
+        let expected = Pairs {
+            both: paths(&["a.json", "b/c.json"]),
+            left_only: paths(&["b/d.json", "e.json"]),
+            right_only: paths(&["b/f.json"]),
+        };
+        assert_eq!(expected, pair(left.path(), right.path(), &filter).unwrap());
+    }
+
+    #[test]
+    fn test_pair_filtered() {
+        let left = tempfile::tempdir().unwrap();
+        let right = tempfile::tempdir().unwrap();
+        create(
+            left.path(),
+            &["a.json", "b/c.json", "b/d.txt", "tmp/e.json"],
+        );
+        create(
+            right.path(),
+            &["a.json", "b/c.json", "b/d.txt", "tmp/e.json"],
+        );
+        let filter = Filter::new(&["*.json".to_string()], &["tmp/**".to_string()]).unwrap();
+        let expected = Pairs {
+            both: paths(&["a.json", "b/c.json"]),
+            ..Default::default()
+        };
+        assert_eq!(expected, pair(left.path(), right.path(), &filter).unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_pair_symlinks() {
+        let left = tempfile::tempdir().unwrap();
+        let right = tempfile::tempdir().unwrap();
+        create(left.path(), &["a.json", "b/c.json"]);
+        create(right.path(), &["a.json", "b/c.json"]);
+        // A link back to the root would loop if it were followed.
+        std::os::unix::fs::symlink(left.path(), left.path().join("b/root")).unwrap();
+        std::os::unix::fs::symlink(left.path().join("a.json"), left.path().join("d.json")).unwrap();
+        let filter = Filter::new(&[], &[]).unwrap();
+        let expected = Pairs {
+            both: paths(&["a.json", "b/c.json"]),
+            left_only: paths(&["d.json"]),
+            ..Default::default()
+        };
+        assert_eq!(expected, pair(left.path(), right.path(), &filter).unwrap());
+    }
+}
@@ -0,0 +1,328 @@
+// Output formats other than the default RFC 6902 patch, which `generate_json_patch` produces.
+// The text and JSON formats include where each change is in the compared files, so editors
+// can jump to it.
+
+pub mod html;
+pub mod junit;
+pub mod markdown;
+pub mod sarif;
+pub mod strings;
+
+use crate::parser::{Locations, Position};
+use crate::path::{self, PathStyle};
+use crate::{JsonPatchOp, Patch};
+use strings::StringDiff;
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum Format {
+    /// JSON Patch (RFC 6902)
+    Patch,
+    /// Human readable changes, with old and new values and their locations
+    Text,
+    /// A JSON report of the changes, with old and new values and their locations
+    Json,
+    /// A self-contained HTML report, with both documents and the changes highlighted
+    Html,
+    /// A GitHub-flavored Markdown table of the changes
+    Markdown,
+    /// JUnit XML, with a test case for each compared file
+    Junit,
+    /// SARIF, with a result for each change
+    Sarif,
+}
+
+impl Format {
+    // Reports are complete documents, so nothing else may be printed around them.
+    pub fn is_report(self) -> bool {
+        self != Format::Patch && self != Format::Text
+    }
+
+    pub fn uses_locations(self) -> bool {
+        matches!(
+            self,
+            Format::Text | Format::Json | Format::Junit | Format::Sarif
+        )
+    }
+}
+
+// Escape text for HTML and XML.
+pub fn escape(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => ret.push_str("&amp;"),
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '"' => ret.push_str("&quot;"),
+            '\'' => ret.push_str("&#39;"),
+            _ => ret.push(c),
+        }
+    }
+    ret
+}
+
+pub struct Source<'a> {
+    pub name: &'a str,
+    pub value: &'a serde_json::Value,
+    pub locations: &'a Locations,
+    pub path_style: PathStyle,
+    // Show what changed within replaced strings, in the text and HTML reports.
+    pub string_diff: Option<StringDiff>,
+}
+
+impl Source<'_> {
+    // Positions are looked up here rather than stored on patches, which stay plain RFC 6902
+    // operations that `apply`, `invert` and `compose` build without any source document.
+    fn locate(&self, path: &str) -> Option<Position> {
+        self.locations.get(path::located(path)).copied()
+    }
+}
+
+// A source with the default path style and no string diffs, for the format tests.
+#[cfg(test)]
+pub(crate) fn test_source<'a>(
+    name: &'a str,
+    value: &'a serde_json::Value,
+    locations: &'a Locations,
+) -> Source<'a> {
+    Source {
+        name,
+        value,
+        locations,
+        path_style: PathStyle::Pointer,
+        string_diff: None,
+    }
+}
+
+// Added values only exist on the right, and removed values only on the left.
+pub fn locate(
+    patch: &Patch,
+    left: &Source,
+    right: &Source,
+) -> (Option<Position>, Option<Position>) {
+    let old = match patch.op {
+        JsonPatchOp::Add => None,
+        _ => left.locate(&patch.path),
+    };
+    let new = match patch.op {
+        JsonPatchOp::Remove => None,
+        _ => right.locate(&patch.path),
+    };
+    (old, new)
+}
+
+// The path of a change, in the requested notation. Added values only exist on the right.
+pub fn display_path(patch: &Patch, left: &Source, right: &Source) -> String {
+    let source = match patch.op {
+        JsonPatchOp::Add => right,
+        _ => left,
+    };
+    path::render(&patch.path, source.path_style, source.value)
+}
+
+pub fn text(patches: &[Patch], left: &Source, right: &Source) -> String {
+    let mut ret = String::new();
+    for patch in patches {
+        let (old, new) = locate(patch, left, right);
+        let locations: Vec<String> = [(left.name, old), (right.name, new)]
+            .iter()
+            .filter_map(|(name, position)| {
+                position.map(|p| format!("{}:{}:{}", name, p.line, p.column))
+            })
+            .collect();
+        ret.push_str(&format!(
+            "{} {}",
+            patch.op,
+            display_path(patch, left, right)
+        ));
+        if !locations.is_empty() {
+            ret.push_str(&format!(" ({})", locations.join(", ")));
+        }
+        ret.push('\n');
+        if let Some(spans) = string_diff(patch, left) {
+            ret.push_str(&format!("  ~ {}\n", strings::plain(&spans)));
+            continue;
+        }
+        if let Some(old_value) = &patch.old_value {
+            ret.push_str(&format!("  - {}\n", old_value));
+        }
+        if let Some(value) = &patch.value {
+            ret.push_str(&format!("  + {}\n", value));
+        }
+    }
+    ret
+}
+
+// What changed within a replaced string, if requested.
+pub fn string_diff<'a>(patch: &'a Patch, source: &Source) -> Option<Vec<strings::Span<'a>>> {
+    match (source.string_diff, &patch.old_value, &patch.value) {
+        (Some(by), Some(serde_json::Value::String(old)), Some(serde_json::Value::String(new))) => {
+            Some(strings::diff(old, new, by))
+        }
+        _ => None,
+    }
+}
+
+pub fn json(patches: &[Patch], left: &Source, right: &Source) -> serde_json::Value {
+    let changes: Vec<serde_json::Value> = patches
+        .iter()
+        .map(|patch| {
+            let (old, new) = locate(patch, left, right);
+            let mut change = serde_json::json!({"op": patch.op.to_string(), "path": display_path(patch, left, right)});
+            if let Some(value) = &patch.value {
+                change["value"] = value.clone();
+            }
+            if let Some(old_value) = &patch.old_value {
+                change["old_value"] = old_value.clone();
+            }
+            if let Some(position) = old {
+                change["left"] =
+                    serde_json::json!({"line": position.line, "column": position.column});
+            }
+            if let Some(position) = new {
+                change["right"] =
+                    serde_json::json!({"line": position.line, "column": position.column});
+            }
+            change
+        })
+        .collect();
+    serde_json::json!({"left": left.name, "right": right.name, "changes": changes})
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::strings::StringDiff;
+    use crate::format::{escape, json, test_source, text, Source};
+    use crate::parser::{parse, Document, Locations};
+    use crate::path::PathStyle;
+    use crate::{compare, Patch};
+
+    fn diff() -> (Document, Document, Vec<Patch>) {
+        let left = parse(
+            "{\n  \"name\": \"nginx\",\n  \"ports\": [80, 443]\n}".as_bytes(),
+            true,
+        )
+        .unwrap();
+        let right = parse(
+            "{\n  \"ports\": [8080],\n  \"image\": \"nginx:1.1\"\n}".as_bytes(),
+            true,
+        )
+        .unwrap();
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&left.value, &right.value, &mut patches);
+        (left, right, patches)
+    }
+
+    #[test]
+    fn test_text() {
+        let (left, right, patches) = diff();
+        let left = test_source("left.json", &left.value, &left.locations);
+        let right = test_source("right.json", &right.value, &right.locations);
+        let expected = r#"remove /name (left.json:2:11)
+  - "nginx"
+replace /ports/0 (left.json:3:13, right.json:2:13)
+  - 80
+  + 8080
+remove /ports/1 (left.json:3:17)
+  - 443
+add /image (right.json:3:12)
+  + "nginx:1.1"
+"#;
+        assert_eq!(expected, text(&patches, &left, &right));
+    }
+
+    #[test]
+    fn test_text_string_diff() {
+        let left = serde_json::json!({"query": "SELECT name FROM users", "limit": 10});
+        let right = serde_json::json!({"query": "SELECT id, name FROM users", "limit": 20});
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&left, &right, &mut patches);
+        let locations = Locations::new();
+        let source = |name, value| Source {
+            string_diff: Some(StringDiff::Word),
+            ..test_source(name, value, &locations)
+        };
+        let expected = r#"replace /query
+  ~ "SELECT {+id, +}name FROM users"
+replace /limit
+  - 10
+  + 20
+"#;
+        assert_eq!(
+            expected,
+            text(
+                &patches,
+                &source("left.json", &left),
+                &source("right.json", &right)
+            )
+        );
+    }
+
+    #[test]
+    fn test_text_path_style() {
+        let (left, right, patches) = diff();
+        let left = Source {
+            path_style: PathStyle::Jq,
+            ..test_source("left.json", &left.value, &left.locations)
+        };
+        let right = Source {
+            path_style: PathStyle::Jq,
+            ..test_source("right.json", &right.value, &right.locations)
+        };
+        let text = text(&patches, &left, &right);
+        let paths: Vec<&str> = text
+            .lines()
+            .filter(|line| !line.starts_with(' '))
+            .map(|line| line.split(' ').nth(1).unwrap())
+            .collect();
+        assert_eq!(vec![".name", ".ports[0]", ".ports[1]", ".image"], paths);
+    }
+
+    #[test]
+    fn test_json() {
+        let (left, right, patches) = diff();
+        let left = test_source("left.json", &left.value, &left.locations);
+        let right = test_source("right.json", &right.value, &right.locations);
+        let expected = serde_json::json!({
+            "left": "left.json",
+            "right": "right.json",
+            "changes": [
+                {
+                    "op": "remove",
+                    "path": "/name",
+                    "old_value": "nginx",
+                    "left": {"line": 2, "column": 11}
+                },
+                {
+                    "op": "replace",
+                    "path": "/ports/0",
+                    "value": 8080,
+                    "old_value": 80,
+                    "left": {"line": 3, "column": 13},
+                    "right": {"line": 2, "column": 13}
+                },
+                {
+                    "op": "remove",
+                    "path": "/ports/1",
+                    "old_value": 443,
+                    "left": {"line": 3, "column": 17}
+                },
+                {
+                    "op": "add",
+                    "path": "/image",
+                    "value": "nginx:1.1",
+                    "right": {"line": 3, "column": 12}
+                }
+            ]
+        });
+        assert_eq!(expected, json(&patches, &left, &right));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;",
+            escape("<a href=\"x\">Tom & Jerry's</a>")
+        );
+    }
+}
@@ -0,0 +1,292 @@
+// A self-contained HTML report, for readers who would rather not read JSON Patch. Both
+// documents are shown as collapsible trees with the changed values highlighted, and an index
+// of the changes links to them. Only the parts of the trees leading to a change start open.
+
+use crate::format::strings::{self, Span};
+use crate::format::{display_path, escape, string_diff, Source};
+use crate::{JsonPatchOp, Patch};
+use std::collections::{HashMap, HashSet};
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; }
+code, .tree { font-family: monospace; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 0.25em 0.5em; text-align: left; vertical-align: top; }
+.documents { display: flex; gap: 2em; }
+.documents section { flex: 1; overflow-x: auto; }
+.tree ul { list-style: none; margin: 0; padding-left: 1.5em; }
+.tree summary { cursor: pointer; }
+.add { background: #e6ffec; }
+.remove { background: #ffebe9; }
+.replace { background: #fff8c5; }
+.reorder { background: #ddf4ff; }
+del { background: #ffc0c0; }
+ins { background: #abf2bc; text-decoration: none; }
+";
+
+pub fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape(title),
+        STYLE,
+        body
+    )
+}
+
+// Which values to highlight in one of the trees, and which containers lead to them.
+struct Marks {
+    classes: HashMap<String, String>,
+    open: HashSet<String>,
+}
+
+impl Marks {
+    fn new<'a>(changes: impl Iterator<Item = &'a Patch>) -> Marks {
+        let mut classes = HashMap::new();
+        let mut open = HashSet::new();
+        for patch in changes {
+            let path = crate::path::located(&patch.path);
+            // A change inside embedded JSON replaces part of the string it is in.
+            let op = if crate::path::outside_embedded(&patch.path) == patch.path {
+                patch.op
+            } else {
+                JsonPatchOp::Replace
+            };
+            classes.insert(path.to_string(), op.to_string());
+            let mut prefix = path;
+            while let Some(i) = prefix.rfind('/') {
+                prefix = &prefix[..i];
+                open.insert(prefix.to_string());
+            }
+        }
+        Marks { classes, open }
+    }
+}
+
+fn tree(
+    out: &mut String,
+    side: &str,
+    key: Option<&str>,
+    value: &serde_json::Value,
+    path: &str,
+    marks: &Marks,
+) {
+    let label = match key {
+        Some(key) => format!("<span class=\"key\">{}</span>: ", escape(key)),
+        None => String::new(),
+    };
+    let id = format!("{}:{}", side, path);
+    let class = marks.classes.get(path).map(String::as_str).unwrap_or("");
+    out.push_str(&format!("<li id=\"{}\" class=\"{}\">", escape(&id), class));
+    let children: Vec<(String, &serde_json::Value)> = match value {
+        serde_json::Value::Object(map) => map.iter().map(|(k, v)| (k.clone(), v)).collect(),
+        serde_json::Value::Array(vec) => vec
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i.to_string(), v))
+            .collect(),
+        _ => {
+            out.push_str(&format!("{}{}</li>\n", label, escape(&value.to_string())));
+            return;
+        }
+    };
+    let (open, close) = if value.is_object() {
+        ("{", "}")
+    } else {
+        ("[", "]")
+    };
+    let expanded = marks.open.contains(path) || key.is_none();
+    out.push_str(&format!(
+        "<details{}><summary>{}{}{} items{}</summary>\n<ul>\n",
+        if expanded { " open" } else { "" },
+        label,
+        open,
+        children.len(),
+        close
+    ));
+    for (child_key, child) in children {
+        let child_path = format!("{}/{}", path, crate::path::escape(&child_key));
+        tree(out, side, Some(&child_key), child, &child_path, marks);
+    }
+    out.push_str("</ul>\n</details></li>\n");
+}
+
+fn value_cell(value: &Option<serde_json::Value>) -> String {
+    match value {
+        Some(value) => format!("<code>{}</code>", escape(&value.to_string())),
+        None => String::new(),
+    }
+}
+
+// A replaced string, with the removed or the added parts marked.
+fn string_cell(spans: &[Span], side: JsonPatchOp) -> String {
+    let mut ret = String::from("<code>&quot;");
+    for span in spans {
+        match (span, side) {
+            (Span::Same(text), _) => ret.push_str(&escape(&strings::escape_json(text))),
+            (Span::Removed(text), JsonPatchOp::Remove) => ret.push_str(&format!(
+                "<del>{}</del>",
+                escape(&strings::escape_json(text))
+            )),
+            (Span::Added(text), JsonPatchOp::Add) => ret.push_str(&format!(
+                "<ins>{}</ins>",
+                escape(&strings::escape_json(text))
+            )),
+            _ => (),
+        }
+    }
+    ret.push_str("&quot;</code>");
+    ret
+}
+
+// The report for one pair of documents, to be wrapped in a `page`.
+pub fn html(patches: &[Patch], left: &Source, right: &Source) -> String {
+    let mut out = format!(
+        "<h1>Comparing <code>{}</code> and <code>{}</code></h1>\n<h2>Changes</h2>\n",
+        escape(left.name),
+        escape(right.name)
+    );
+    if patches.is_empty() {
+        out.push_str("<p>No differences were detected.</p>\n");
+    } else {
+        out.push_str(
+            "<table>\n<tr><th>op</th><th>path</th><th>old value</th><th>new value</th></tr>\n",
+        );
+        for patch in patches {
+            // Removed values are only in the left tree; everything else is in the right one.
+            let side = if patch.op == JsonPatchOp::Remove {
+                left.name
+            } else {
+                right.name
+            };
+            let (old_cell, new_cell) = match string_diff(patch, left) {
+                Some(spans) => (
+                    string_cell(&spans, JsonPatchOp::Remove),
+                    string_cell(&spans, JsonPatchOp::Add),
+                ),
+                None => (value_cell(&patch.old_value), value_cell(&patch.value)),
+            };
+            out.push_str(&format!(
+                "<tr class=\"{}\"><td>{}</td><td><a href=\"#{}\">{}</a></td><td>{}</td><td>{}</td></tr>\n",
+                patch.op,
+                patch.op,
+                escape(&format!("{}:{}", side, crate::path::located(&patch.path))),
+                escape(&display_path(patch, left, right)),
+                old_cell,
+                new_cell
+            ));
+        }
+        out.push_str("</table>\n");
+    }
+    let left_marks = Marks::new(patches.iter().filter(|p| p.op != JsonPatchOp::Add));
+    let right_marks = Marks::new(patches.iter().filter(|p| p.op != JsonPatchOp::Remove));
+    out.push_str("<div class=\"documents\">\n");
+    for (source, marks) in [(left, left_marks), (right, right_marks)] {
+        out.push_str(&format!(
+            "<section>\n<h2><code>{}</code></h2>\n<ul class=\"tree\">\n",
+            escape(source.name)
+        ));
+        tree(&mut out, source.name, None, source.value, "", &marks);
+        out.push_str("</ul>\n</section>\n");
+    }
+    out.push_str("</div>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::html::html;
+    use crate::format::strings::StringDiff;
+    use crate::format::{test_source, Source};
+    use crate::parser::Locations;
+    use crate::{compare, JsonPatchOp, Patch};
+
+    #[test]
+    fn test_html() {
+        let left = serde_json::json!({"spec": {"image": "nginx:1.0", "port": 80}, "name": "web"});
+        let right = serde_json::json!({"spec": {"image": "<nginx:1.1>"}, "name": "web"});
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&left, &right, &mut patches);
+        let locations = Locations::new();
+        let left = test_source("left.json", &left, &locations);
+        let right = test_source("right.json", &right, &locations);
+        let html = html(&patches, &left, &right);
+        // The index links to the changed values, which are highlighted in each tree.
+        assert!(html.contains(
+            "<tr class=\"replace\"><td>replace</td><td><a href=\"#right.json:/spec/image\">/spec/image</a></td>\
+             <td><code>&quot;nginx:1.0&quot;</code></td><td><code>&quot;&lt;nginx:1.1&gt;&quot;</code></td></tr>"
+        ));
+        assert!(html.contains("<a href=\"#left.json:/spec/port\">/spec/port</a>"));
+        assert!(html.contains(
+            "<li id=\"left.json:/spec/image\" class=\"replace\"><span class=\"key\">image</span>: &quot;nginx:1.0&quot;</li>"
+        ));
+        assert!(html.contains(
+            "<li id=\"right.json:/spec/image\" class=\"replace\"><span class=\"key\">image</span>: &quot;&lt;nginx:1.1&gt;&quot;</li>"
+        ));
+        assert!(html.contains("<li id=\"left.json:/spec/port\" class=\"remove\">"));
+        // Only the containers leading to a change start open.
+        assert!(html.contains(
+            "<details open><summary><span class=\"key\">spec</span>: {2 items}</summary>"
+        ));
+        assert!(html.contains("<li id=\"left.json:/name\" class=\"\"><span class=\"key\">name</span>: &quot;web&quot;</li>"));
+    }
+
+    #[test]
+    fn test_html_root() {
+        let locations = Locations::new();
+        for (op, value, old_value, side) in [
+            (
+                JsonPatchOp::Add,
+                Some(serde_json::json!(1)),
+                None,
+                "right.json",
+            ),
+            (
+                JsonPatchOp::Remove,
+                None,
+                Some(serde_json::json!(1)),
+                "left.json",
+            ),
+        ] {
+            let patches = vec![Patch {
+                op,
+                path: "/".to_string(),
+                value,
+                old_value,
+            }];
+            let document = serde_json::json!(1);
+            let html = html(
+                &patches,
+                &test_source("left.json", &document, &locations),
+                &test_source("right.json", &document, &locations),
+            );
+            assert!(html.contains(&format!("<tr class=\"{}\">", op)), "{}", op);
+            assert!(
+                html.contains(&format!("<li id=\"{}:\" class=\"{}\">", side, op)),
+                "{}",
+                op
+            );
+        }
+    }
+
+    #[test]
+    fn test_html_string_diff() {
+        let left = serde_json::json!({"image": "nginx:1.0"});
+        let right = serde_json::json!({"image": "nginx:1.1"});
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&left, &right, &mut patches);
+        let locations = Locations::new();
+        let source = |name, value| Source {
+            string_diff: Some(StringDiff::Char),
+            ..test_source(name, value, &locations)
+        };
+        let html = html(
+            &patches,
+            &source("left.json", &left),
+            &source("right.json", &right),
+        );
+        assert!(html.contains(
+            "<td><code>&quot;nginx:1.<del>0</del>&quot;</code></td><td><code>&quot;nginx:1.<ins>1</ins>&quot;</code></td>"
+        ));
+    }
+}
@@ -0,0 +1,94 @@
+// JUnit XML, so that CI systems using jsondiff as a golden file checker can show each compared
+// file as a test case, failing when the files differ.
+
+use crate::format::escape;
+
+pub struct TestCase {
+    pub name: String,
+    // A short message and the details, when the files differ.
+    pub failure: Option<(String, String)>,
+}
+
+// Escaped text, with the characters that XML 1.0 doesn't allow (most control characters) replaced
+// by U+FFFD, as file names and error messages may contain them.
+fn text(text: &str) -> String {
+    let allowed = |c: char| matches!(c, '\t' | '\n' | '\r' | ' '..='\u{fffd}' | '\u{10000}'..);
+    let text: String = text
+        .chars()
+        .map(|c| if allowed(c) { c } else { '\u{fffd}' })
+        .collect();
+    escape(&text)
+}
+
+pub fn junit(cases: &[TestCase]) -> String {
+    let failures = cases.iter().filter(|case| case.failure.is_some()).count();
+    let mut ret = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <testsuites tests=\"{}\" failures=\"{}\">\n\
+         <testsuite name=\"jsondiff\" tests=\"{}\" failures=\"{}\">\n",
+        cases.len(),
+        failures,
+        cases.len(),
+        failures
+    );
+    for case in cases {
+        ret.push_str(&format!(
+            "<testcase classname=\"jsondiff\" name=\"{}\"",
+            text(&case.name)
+        ));
+        match &case.failure {
+            Some((message, details)) => ret.push_str(&format!(
+                ">\n<failure type=\"difference\" message=\"{}\">{}</failure>\n</testcase>\n",
+                text(message),
+                text(details)
+            )),
+            None => ret.push_str("/>\n"),
+        }
+    }
+    ret.push_str("</testsuite>\n</testsuites>\n");
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::junit::{junit, text, TestCase};
+
+    #[test]
+    fn test_junit() {
+        let cases = vec![
+            TestCase {
+                name: "same.json".to_string(),
+                failure: None,
+            },
+            TestCase {
+                name: "changed.json".to_string(),
+                failure: Some((
+                    "1 difference".to_string(),
+                    "replace /a\n  - \"<old>\"\n  + \"new\"\n".to_string(),
+                )),
+            },
+        ];
+        let expected = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites tests="2" failures="1">
+<testsuite name="jsondiff" tests="2" failures="1">
+<testcase classname="jsondiff" name="same.json"/>
+<testcase classname="jsondiff" name="changed.json">
+<failure type="difference" message="1 difference">replace /a
+  - &quot;&lt;old&gt;&quot;
+  + &quot;new&quot;
+</failure>
+</testcase>
+</testsuite>
+</testsuites>
+"#;
+        assert_eq!(expected, junit(&cases));
+    }
+
+    #[test]
+    fn test_text() {
+        assert_eq!(
+            "a\tb\n\u{fffd}c\u{fffd}&amp;",
+            text("a\tb\n\u{1b}c\u{ffff}&")
+        );
+    }
+}
@@ -0,0 +1,153 @@
+// GitHub-flavored Markdown, e.g. for a bot to post the changes as a pull request comment.
+
+use crate::format::{display_path, escape, Source};
+use crate::Patch;
+
+// Longer values would make the table unreadable, so they are shown in full below it instead.
+const MAX_VALUE_LENGTH: usize = 80;
+
+// Wrap text in a code span whose fence is longer than any run of backticks in it. Pipes have to
+// be escaped even inside code spans, or they would end the table cell.
+pub fn code(text: &str) -> String {
+    let longest = text
+        .split(|c| c != '`')
+        .map(|run| run.len())
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest + 1);
+    // A code span that starts or ends with a backtick needs a space to separate it from the fence.
+    let padding = if text.starts_with('`') || text.ends_with('`') {
+        " "
+    } else {
+        ""
+    };
+    format!(
+        "{}{}{}{}{}",
+        fence,
+        padding,
+        text.replace('|', "\\|"),
+        padding,
+        fence
+    )
+}
+
+// A fenced code block, collapsed under a summary naming the value.
+fn details(path: &str, label: &str, value: &serde_json::Value) -> String {
+    let pretty = serde_json::to_string_pretty(value).expect("values serialize");
+    let longest = pretty
+        .split(|c| c != '`')
+        .map(|run| run.len())
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat((longest + 1).max(3));
+    format!(
+        "<details>\n<summary><code>{}</code> {}</summary>\n\n{}json\n{}\n{}\n\n</details>\n",
+        escape(path),
+        label,
+        fence,
+        pretty,
+        fence
+    )
+}
+
+// The value in a table cell, or a pointer to its details below the table if it is too long.
+fn value_cell(
+    value: &Option<serde_json::Value>,
+    path: &str,
+    label: &str,
+    below: &mut String,
+) -> String {
+    let value = match value {
+        Some(value) => value,
+        None => return String::new(),
+    };
+    let text = value.to_string();
+    if text.chars().count() > MAX_VALUE_LENGTH {
+        below.push_str(&details(path, label, value));
+        "(below)".to_string()
+    } else {
+        code(&text)
+    }
+}
+
+pub fn markdown(patches: &[Patch], left: &Source, right: &Source) -> String {
+    let mut ret = format!(
+        "### Comparing {} and {}\n\n",
+        code(left.name),
+        code(right.name)
+    );
+    if patches.is_empty() {
+        ret.push_str("No differences were detected.\n");
+        return ret;
+    }
+    ret.push_str("| path | op | old value | new value |\n| --- | --- | --- | --- |\n");
+    let mut below = String::new();
+    for patch in patches {
+        let path = display_path(patch, left, right);
+        let old_value = value_cell(&patch.old_value, &path, "old value", &mut below);
+        let value = value_cell(&patch.value, &path, "new value", &mut below);
+        ret.push_str(&format!(
+            "| {} | {} | {} | {} |\n",
+            code(&path),
+            patch.op,
+            old_value,
+            value
+        ));
+    }
+    if !below.is_empty() {
+        ret.push('\n');
+        ret.push_str(&below);
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::markdown::{code, markdown};
+    use crate::format::test_source;
+    use crate::parser::Locations;
+    use crate::{compare, Patch};
+
+    #[test]
+    fn test_code() {
+        assert_eq!("`a`", code("a"));
+        assert_eq!("``a`b``", code("a`b"));
+        assert_eq!("``` ``a` ```", code("``a`"));
+        assert_eq!("`\"a\\|b\"`", code("\"a|b\""));
+    }
+
+    #[test]
+    fn test_markdown() {
+        let left = serde_json::json!({"image": "nginx:1.0", "port": 80, "description": "a"});
+        let right = serde_json::json!({
+            "image": "nginx:1.1",
+            "description": "There are strange things done in the midnight sun by the men who moil for gold; ```",
+            "tags": ["a|b"]
+        });
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&left, &right, &mut patches);
+        let locations = Locations::new();
+        let left = test_source("left.json", &left, &locations);
+        let right = test_source("right.json", &right, &locations);
+        let expected = "\
+### Comparing `left.json` and `right.json`
+
+| path | op | old value | new value |
+| --- | --- | --- | --- |
+| `/image` | replace | `\"nginx:1.0\"` | `\"nginx:1.1\"` |
+| `/port` | remove | `80` |  |
+| `/description` | replace | `\"a\"` | (below) |
+| `/tags` | add |  | `[\"a\\|b\"]` |
+
+<details>
+<summary><code>/description</code> new value</summary>
+
+````json
+\"There are strange things done in the midnight sun by the men who moil for gold; ```\"
+````
+
+</details>
+";
+        assert_eq!(expected, markdown(&patches, &left, &right));
+    }
+}
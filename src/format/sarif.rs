@@ -0,0 +1,169 @@
+// SARIF (Static Analysis Results Interchange Format) 2.1.0, so that each change shows up as a
+// result at its location in code scanning UIs.
+
+use crate::format::{display_path, locate, Source};
+use crate::{JsonPatchOp, Patch};
+
+const RULES: [(&str, &str); 4] = [
+    ("add", "A value was added"),
+    ("remove", "A value was removed"),
+    ("replace", "A value was replaced"),
+    ("reorder", "The keys of an object were reordered"),
+];
+
+// A relative URI reference for a file path, with path separators as "/" and everything but
+// unreserved characters percent-encoded, e.g. "snapshots/a b#1.json" becomes
+// "snapshots/a%20b%231.json".
+fn uri_reference(path: &str) -> String {
+    let mut ret = String::with_capacity(path.len());
+    for c in path.chars() {
+        let c = if c == std::path::MAIN_SEPARATOR {
+            '/'
+        } else {
+            c
+        };
+        if c.is_ascii_alphanumeric() || "-._~/".contains(c) {
+            ret.push(c);
+        } else {
+            let mut bytes = [0; 4];
+            for byte in c.encode_utf8(&mut bytes).bytes() {
+                ret.push_str(&format!("%{:02X}", byte));
+            }
+        }
+    }
+    ret
+}
+
+// A result without a region, for a whole file that only exists on one side.
+pub fn file_result(op: &JsonPatchOp, uri: &str, message: &str) -> serde_json::Value {
+    serde_json::json!({
+        "ruleId": op.to_string(),
+        "level": "error",
+        "message": {"text": message},
+        "locations": [{"physicalLocation": {"artifactLocation": {"uri": uri_reference(uri)}}}],
+    })
+}
+
+// Removed values are located in the left file; everything else in the right one.
+pub fn results(patches: &[Patch], left: &Source, right: &Source) -> Vec<serde_json::Value> {
+    patches
+        .iter()
+        .map(|patch| {
+            let (old, new) = locate(patch, left, right);
+            let (uri, position) = match patch.op {
+                JsonPatchOp::Remove => (left.name, old),
+                _ => (right.name, new),
+            };
+            let path = display_path(patch, left, right);
+            let mut message = format!("{} {}", patch.op, path);
+            match (&patch.old_value, &patch.value) {
+                (Some(old_value), Some(value)) => {
+                    message.push_str(&format!(": {} -> {}", old_value, value))
+                }
+                (Some(value), None) | (None, Some(value)) => {
+                    message.push_str(&format!(": {}", value))
+                }
+                (None, None) => {}
+            }
+            let mut location = serde_json::json!({
+                "physicalLocation": {"artifactLocation": {"uri": uri_reference(uri)}},
+                "logicalLocations": [{"fullyQualifiedName": path}],
+            });
+            if let Some(position) = position {
+                location["physicalLocation"]["region"] = serde_json::json!({
+                    "startLine": position.line,
+                    "startColumn": position.column,
+                });
+            }
+            serde_json::json!({
+                "ruleId": patch.op.to_string(),
+                "level": "error",
+                "message": {"text": message},
+                "locations": [location],
+            })
+        })
+        .collect()
+}
+
+pub fn sarif(results: Vec<serde_json::Value>) -> serde_json::Value {
+    let rules: Vec<serde_json::Value> = RULES
+        .iter()
+        .map(|(id, description)| serde_json::json!({"id": id, "shortDescription": {"text": description}}))
+        .collect();
+    serde_json::json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_HOMEPAGE"),
+                    "rules": rules,
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::sarif::{results, sarif, uri_reference};
+    use crate::format::test_source;
+    use crate::parser::parse;
+    use crate::{compare, Patch};
+
+    #[test]
+    fn test_sarif() {
+        let left = parse(
+            "{\n  \"image\": \"nginx:1.0\",\n  \"port\": 80\n}".as_bytes(),
+            true,
+        )
+        .unwrap();
+        let right = parse("{\n  \"image\": \"nginx:1.1\"\n}".as_bytes(), true).unwrap();
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&left.value, &right.value, &mut patches);
+        let left = test_source("left.json", &left.value, &left.locations);
+        let right = test_source("right.json", &right.value, &right.locations);
+        let sarif = sarif(results(&patches, &left, &right));
+        assert_eq!("2.1.0", sarif["version"]);
+        assert_eq!("jsondiff", sarif["runs"][0]["tool"]["driver"]["name"]);
+        let expected = serde_json::json!([
+            {
+                "ruleId": "replace",
+                "level": "error",
+                "message": {"text": "replace /image: \"nginx:1.0\" -> \"nginx:1.1\""},
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": {"uri": "right.json"},
+                        "region": {"startLine": 2, "startColumn": 12}
+                    },
+                    "logicalLocations": [{"fullyQualifiedName": "/image"}]
+                }]
+            },
+            {
+                "ruleId": "remove",
+                "level": "error",
+                "message": {"text": "remove /port: 80"},
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": {"uri": "left.json"},
+                        "region": {"startLine": 3, "startColumn": 11}
+                    },
+                    "logicalLocations": [{"fullyQualifiedName": "/port"}]
+                }]
+            }
+        ]);
+        assert_eq!(expected, sarif["runs"][0]["results"]);
+    }
+
+    #[test]
+    fn test_uri_reference() {
+        assert_eq!(
+            "snapshots/a%20b%231.json",
+            uri_reference("snapshots/a b#1.json")
+        );
+        assert_eq!("caf%C3%A9%3F.json", uri_reference("café?.json"));
+    }
+}
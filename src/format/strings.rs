@@ -0,0 +1,258 @@
+// Differences within strings, so that a change to a long string (a description, embedded SQL, a
+// template) shows what changed rather than only the whole old and new strings. Only reports use
+// them; JSON Patch still replaces the whole string.
+//
+// Strings are compared as sequences of words or characters with Myers' algorithm, which finds
+// the fewest insertions and removals.
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum StringDiff {
+    /// Compare strings word by word
+    Word,
+    /// Compare strings character by character
+    Char,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Span<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+// Finding the fewest changes takes memory proportional to their number times the length of the
+// strings, so beyond this, strings are reported as replaced whole.
+const MAX_TRACE: usize = 10_000_000;
+
+// Byte ranges of the words (runs of letters, digits and underscores), runs of whitespace and
+// other characters of `text`, or of each character.
+fn split(text: &str, by: StringDiff) -> Vec<(usize, usize)> {
+    let class = |c: char| {
+        if by == StringDiff::Char {
+            None
+        } else if c.is_alphanumeric() || c == '_' {
+            Some(0)
+        } else if c.is_whitespace() {
+            Some(1)
+        } else {
+            None
+        }
+    };
+    let mut ret: Vec<(usize, usize)> = Vec::new();
+    let mut previous = None;
+    for (i, c) in text.char_indices() {
+        let current = class(c);
+        match ret.last_mut() {
+            Some(last) if current.is_some() && current == previous => last.1 = i + c.len_utf8(),
+            _ => ret.push((i, i + c.len_utf8())),
+        }
+        previous = current;
+    }
+    ret
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Edit {
+    Same,
+    Removed,
+    Added,
+}
+
+// The edits that turn `old` into `new`, or None if there are too many to find.
+fn myers(old: &[&str], new: &[&str]) -> Option<Vec<Edit>> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = n + m;
+    let offset = max + 1;
+    let mut v = vec![0isize; 2 * max as usize + 3];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    'search: for d in 0..=max {
+        if (trace.len() + 1) * v.len() > MAX_TRACE {
+            return None;
+        }
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let i = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                v[i + 1]
+            } else {
+                v[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+    let mut ret: Vec<Edit> = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let (d, k) = (d as isize, x - y);
+        let previous =
+            if k == -d || (k != d && v[(k - 1 + offset) as usize] < v[(k + 1 + offset) as usize]) {
+                k + 1
+            } else {
+                k - 1
+            };
+        let previous_x = v[(previous + offset) as usize];
+        let previous_y = previous_x - previous;
+        while x > previous_x && y > previous_y {
+            ret.push(Edit::Same);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            ret.push(if x == previous_x {
+                Edit::Added
+            } else {
+                Edit::Removed
+            });
+        }
+        (x, y) = (previous_x, previous_y);
+    }
+    ret.reverse();
+    Some(ret)
+}
+
+// Each run of changes between unchanged text is reported as one removal and one addition.
+pub fn diff<'a>(old: &'a str, new: &'a str, by: StringDiff) -> Vec<Span<'a>> {
+    let (old_tokens, new_tokens) = (split(old, by), split(new, by));
+    let text = |text: &'a str, tokens: &[(usize, usize)]| -> Vec<&'a str> {
+        tokens
+            .iter()
+            .map(|(start, end)| &text[*start..*end])
+            .collect()
+    };
+    let (old_text, new_text) = (text(old, &old_tokens), text(new, &new_tokens));
+    // Long strings usually change in a few places, so the common start and end are skipped.
+    let prefix = old_text
+        .iter()
+        .zip(&new_text)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = old_text[prefix..]
+        .iter()
+        .rev()
+        .zip(new_text[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let middle = myers(
+        &old_text[prefix..old_text.len() - suffix],
+        &new_text[prefix..new_text.len() - suffix],
+    );
+    let edits = match middle {
+        Some(middle) => [vec![Edit::Same; prefix], middle, vec![Edit::Same; suffix]].concat(),
+        None => return vec![Span::Removed(old), Span::Added(new)],
+    };
+    let mut ret: Vec<Span<'a>> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let mut removed: Option<(usize, usize)> = None;
+    let mut added: Option<(usize, usize)> = None;
+    let mut same: Option<(usize, usize)> = None;
+    let extend = |range: &mut Option<(usize, usize)>, token: (usize, usize)| {
+        *range = Some(range.map_or(token, |(start, _)| (start, token.1)));
+    };
+    for edit in edits.into_iter().chain([Edit::Same]) {
+        if edit == Edit::Same {
+            if let Some((start, end)) = removed.take() {
+                ret.push(Span::Removed(&old[start..end]));
+            }
+            if let Some((start, end)) = added.take() {
+                ret.push(Span::Added(&new[start..end]));
+            }
+        } else if let Some((start, end)) = same.take() {
+            ret.push(Span::Same(&old[start..end]));
+        }
+        match edit {
+            Edit::Same if i < old_tokens.len() => {
+                extend(&mut same, old_tokens[i]);
+                i += 1;
+                j += 1;
+            }
+            Edit::Same => (),
+            Edit::Removed => {
+                extend(&mut removed, old_tokens[i]);
+                i += 1;
+            }
+            Edit::Added => {
+                extend(&mut added, new_tokens[j]);
+                j += 1;
+            }
+        }
+    }
+    if let Some((start, end)) = same {
+        ret.push(Span::Same(&old[start..end]));
+    }
+    ret
+}
+
+// The inside of a JSON string.
+pub fn escape_json(text: &str) -> String {
+    let quoted = serde_json::Value::String(text.to_string()).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+// A JSON string marked up the way `git diff --word-diff=plain` does: [-removed-]{+added+}.
+pub fn plain(spans: &[Span]) -> String {
+    let mut ret = String::from('"');
+    for span in spans {
+        match span {
+            Span::Same(text) => ret.push_str(&escape_json(text)),
+            Span::Removed(text) => ret.push_str(&format!("[-{}-]", escape_json(text))),
+            Span::Added(text) => ret.push_str(&format!("{{+{}+}}", escape_json(text))),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::strings::{diff, plain, Span, StringDiff};
+
+    #[test]
+    fn test_diff_words() {
+        let expected = vec![
+            Span::Same("SELECT "),
+            Span::Added("id, "),
+            Span::Same("name FROM users WHERE "),
+            Span::Removed("active"),
+            Span::Added("deleted_at IS NULL"),
+        ];
+        assert_eq!(
+            expected,
+            diff(
+                "SELECT name FROM users WHERE active",
+                "SELECT id, name FROM users WHERE deleted_at IS NULL",
+                StringDiff::Word
+            )
+        );
+    }
+
+    #[test]
+    fn test_diff_chars() {
+        let expected = vec![
+            Span::Same("colo"),
+            Span::Added("u"),
+            Span::Same("r: gr"),
+            Span::Removed("a"),
+            Span::Added("e"),
+            Span::Same("y ✓"),
+        ];
+        assert_eq!(
+            expected,
+            diff("color: gray ✓", "colour: grey ✓", StringDiff::Char)
+        );
+        assert_eq!(vec![Span::Added("new")], diff("", "new", StringDiff::Char));
+    }
+
+    #[test]
+    fn test_plain() {
+        let spans = diff("say \"hi\"\n", "say \"hello\"\n", StringDiff::Word);
+        assert_eq!(r#""say \"[-hi-]{+hello+}\"\n""#, plain(&spans));
+    }
+}
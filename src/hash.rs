@@ -0,0 +1,213 @@
+// Structural hashes of the containers in a pair of documents, so that identical subtrees can be
+// skipped without walking them. A container's hash is built from the hashes of its members
+// (Merkle-style), so both documents are hashed in a single pass. Hashes are consistent with
+// `equal`: numbers are hashed by value unless they are compared exactly, and the order of an
+// object's keys only counts when reordering is reported.
+//
+// Different hashes mean different values, so most changed containers are found without walking
+// them. Equal hashes are confirmed with `equal`, so that a collision can't hide a change; only
+// subtrees that really are equal are walked in full.
+
+use crate::{equal, members, normalize_number, CompareOptions};
+use foldhash::quality::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, BuildHasherDefault, Hash, Hasher};
+
+type Containers = HashMap<usize, u64, BuildHasherDefault<AddressHasher>>;
+
+// Keyed by the address of each container, which is stable while the documents are borrowed.
+pub struct Hashes {
+    left: Containers,
+    right: Containers,
+}
+
+fn address(value: &serde_json::Value) -> usize {
+    value as *const serde_json::Value as usize
+}
+
+impl Hashes {
+    // With `options.parallel`, the two documents are hashed concurrently.
+    pub fn new(
+        left: &serde_json::Value,
+        right: &serde_json::Value,
+        options: &CompareOptions,
+    ) -> Hashes {
+        let state = RandomState::default();
+        let hash = |document: &serde_json::Value| {
+            let mut walk = Walk {
+                state: &state,
+                containers: Containers::default(),
+            };
+            if document.is_object() || document.is_array() {
+                walk.container(document, options);
+            }
+            walk.containers
+        };
+        let (left, right) = if options.parallel {
+            rayon::join(|| hash(left), || hash(right))
+        } else {
+            (hash(left), hash(right))
+        };
+        Hashes { left, right }
+    }
+
+    // Containers with different hashes differ; scalars are cheap enough to compare directly.
+    pub fn equal(
+        &self,
+        left: &serde_json::Value,
+        right: &serde_json::Value,
+        options: &CompareOptions,
+    ) -> bool {
+        match (
+            self.left.get(&address(left)),
+            self.right.get(&address(right)),
+        ) {
+            (Some(left), Some(right)) if left != right => false,
+            _ => equal(left, right, options),
+        }
+    }
+}
+
+struct Walk<'a> {
+    state: &'a RandomState,
+    containers: Containers,
+}
+
+impl Walk<'_> {
+    fn container(&mut self, value: &serde_json::Value, options: &CompareOptions) -> u64 {
+        let mut hasher = self.state.build_hasher();
+        match value {
+            serde_json::Value::Object(map) => {
+                hasher.write_u8(b'{');
+                // Members treated as missing aren't hashed, as `equal` ignores them.
+                hasher.write_usize(members(map, options).count());
+                let mut sum = 0u64;
+                for (key, value) in members(map, options) {
+                    let mut member = self.state.build_hasher();
+                    key.hash(&mut member);
+                    self.write(&mut member, value, options);
+                    if options.report_reordering {
+                        hasher.write_u64(member.finish());
+                    } else {
+                        // Addition doesn't depend on the order of the members.
+                        sum = sum.wrapping_add(member.finish());
+                    }
+                }
+                hasher.write_u64(sum);
+            }
+            serde_json::Value::Array(vec) => {
+                hasher.write_u8(b'[');
+                hasher.write_usize(vec.len());
+                for value in vec {
+                    self.write(&mut hasher, value, options);
+                }
+            }
+            _ => unreachable!("not a container"),
+        }
+        let ret = hasher.finish();
+        self.containers.insert(address(value), ret);
+        ret
+    }
+
+    // Scalars are written into their container's hash directly.
+    fn write(
+        &mut self,
+        hasher: &mut foldhash::quality::FoldHasher<'_>,
+        value: &serde_json::Value,
+        options: &CompareOptions,
+    ) {
+        match value {
+            serde_json::Value::Object(_) | serde_json::Value::Array(_) => {
+                hasher.write_u64(self.container(value, options))
+            }
+            serde_json::Value::Number(number) => {
+                match normalize_number(number.as_str()).filter(|_| !options.exact_numbers) {
+                    Some(normalized) => {
+                        hasher.write_u8(b'0');
+                        normalized.hash(hasher);
+                    }
+                    None => {
+                        hasher.write_u8(b'1');
+                        number.as_str().hash(hasher);
+                    }
+                }
+            }
+            serde_json::Value::String(string) => {
+                hasher.write_u8(b'"');
+                string.hash(hasher);
+            }
+            serde_json::Value::Bool(bool) => {
+                hasher.write_u8(b'b');
+                hasher.write_u8(u8::from(*bool));
+            }
+            serde_json::Value::Null => hasher.write_u8(b'n'),
+        }
+    }
+}
+
+// Addresses are already unique, so they only need mixing to spread them over the table.
+#[derive(Default)]
+struct AddressHasher(u64);
+
+impl Hasher for AddressHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, _bytes: &[u8]) {
+        unreachable!("only addresses are hashed")
+    }
+
+    fn write_usize(&mut self, address: usize) {
+        let mixed = (address as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        self.0 = mixed ^ (mixed >> 32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hash::{address, Containers, Hashes};
+    use crate::CompareOptions;
+
+    #[test]
+    fn test_hashes() {
+        let left: serde_json::Value =
+            serde_json::from_str(r#"{"a": {"b": 1.0, "c": [1, 2]}, "d": {"e": null}}"#).unwrap();
+        let right: serde_json::Value =
+            serde_json::from_str(r#"{"a": {"c": [1, 2], "b": 1}, "d": {"e": false}}"#).unwrap();
+        let options = CompareOptions::default();
+        let hashes = Hashes::new(&left, &right, &options);
+        assert!(hashes.equal(&left["a"], &right["a"], &options));
+        assert!(hashes.equal(&left["a"]["c"], &right["a"]["c"], &options));
+        assert!(!hashes.equal(&left["d"], &right["d"], &options));
+        assert!(!hashes.equal(&left, &right, &options));
+        // Not if numbers are compared exactly or key order matters.
+        for options in [
+            CompareOptions {
+                exact_numbers: true,
+                ..Default::default()
+            },
+            CompareOptions {
+                report_reordering: true,
+                ..Default::default()
+            },
+        ] {
+            let hashes = Hashes::new(&left, &right, &options);
+            assert!(!hashes.equal(&left["a"], &right["a"], &options));
+            assert!(hashes.equal(&left["a"]["c"], &right["a"]["c"], &options));
+        }
+    }
+
+    #[test]
+    fn test_hashes_collision() {
+        let left = serde_json::json!({"a": 1});
+        let right = serde_json::json!({"a": 2});
+        let mut hashes = Hashes {
+            left: Containers::default(),
+            right: Containers::default(),
+        };
+        hashes.left.insert(address(&left), 0);
+        hashes.right.insert(address(&right), 0);
+        assert!(!hashes.equal(&left, &right, &CompareOptions::default()));
+    }
+}
@@ -0,0 +1,118 @@
+// Inverts patches, so that applying a patch and then its inverse leaves a document as it was.
+// Removing or replacing a value can only be undone with the value it had, so the patches must
+// have old values, as `compare` records and extended JSON Patches include.
+
+use crate::apply::Error;
+use crate::{JsonPatchOp, Patch};
+
+pub fn invert(patches: &[Patch]) -> Result<Vec<Patch>, Error> {
+    patches.iter().rev().map(invert_one).collect()
+}
+
+fn invert_one(patch: &Patch) -> Result<Patch, Error> {
+    let error = |message: &str| Error {
+        path: patch.path.clone(),
+        message: message.to_string(),
+    };
+    let old_value = || {
+        patch
+            .old_value
+            .clone()
+            .ok_or_else(|| error("missing old value"))
+    };
+    let (op, value, old_value) = match patch.op {
+        // The index an element was appended at depends on the document.
+        JsonPatchOp::Add if patch.path.ends_with("/-") => {
+            return Err(error("cannot invert an append"));
+        }
+        // Adding an object member that already existed replaced it.
+        JsonPatchOp::Add if patch.old_value.is_some() => (
+            JsonPatchOp::Replace,
+            patch.old_value.clone(),
+            patch.value.clone(),
+        ),
+        JsonPatchOp::Add => (JsonPatchOp::Remove, None, patch.value.clone()),
+        JsonPatchOp::Remove => (JsonPatchOp::Add, Some(old_value()?), None),
+        JsonPatchOp::Replace => (
+            JsonPatchOp::Replace,
+            Some(old_value()?),
+            patch.value.clone(),
+        ),
+        JsonPatchOp::Reorder => (
+            JsonPatchOp::Reorder,
+            Some(old_value()?),
+            patch.value.clone(),
+        ),
+    };
+    Ok(Patch {
+        op,
+        path: patch.path.clone(),
+        value,
+        old_value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::apply::{apply, Error};
+    use crate::invert::invert;
+    use crate::{compare, parse_json_patch, Patch};
+
+    #[test]
+    fn test_invert_round_trip() {
+        let cases = [
+            (
+                serde_json::json!({"a": [1, 2, 3, 4], "b": {"c": 1}, "d": "x"}),
+                serde_json::json!({"a": [1, 5], "b": {"c": 2, "e": [6]}}),
+            ),
+            (serde_json::json!([1, 2]), serde_json::json!([1, 2, 3, 4])),
+            (serde_json::json!({"a": 1}), serde_json::json!("b")),
+        ];
+        for (left, right) in cases {
+            let mut patches: Vec<Patch> = Vec::new();
+            compare(&left, &right, &mut patches);
+            let mut document = right.clone();
+            apply(&mut document, &invert(&patches).unwrap()).unwrap();
+            assert_eq!(left, document);
+            // Inverting twice gives the original patches.
+            assert_eq!(patches, invert(&invert(&patches).unwrap()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_invert_add_existing() {
+        let json_patch = serde_json::json!([
+            {"op": "add", "path": "/a", "value": 5, "old_value": 1},
+            {"op": "add", "path": "/b", "value": 2}
+        ]);
+        let patches = parse_json_patch(&json_patch).unwrap();
+        let original = serde_json::json!({"a": 1});
+        let mut document = original.clone();
+        apply(&mut document, &patches).unwrap();
+        assert_eq!(serde_json::json!({"a": 5, "b": 2}), document);
+        apply(&mut document, &invert(&patches).unwrap()).unwrap();
+        assert_eq!(original, document);
+    }
+
+    #[test]
+    fn test_invert_errors() {
+        let cases = [
+            (
+                serde_json::json!([{"op": "replace", "path": "/a", "value": 1}]),
+                "missing old value",
+            ),
+            (
+                serde_json::json!([{"op": "add", "path": "/a/-", "value": 1}]),
+                "cannot invert an append",
+            ),
+        ];
+        for (json_patch, message) in cases {
+            let patches = parse_json_patch(&json_patch).unwrap();
+            let expected = Error {
+                path: patches[0].path.clone(),
+                message: message.to_string(),
+            };
+            assert_eq!(Err(expected), invert(&patches));
+        }
+    }
+}
@@ -0,0 +1,1081 @@
+pub mod apply;
+pub mod compose;
+pub mod dir;
+pub mod format;
+mod hash;
+pub mod invert;
+pub mod merge;
+pub mod normalize;
+pub mod parser;
+pub mod path;
+pub mod rebase;
+pub mod stat;
+pub mod stream;
+
+use rayon::prelude::*;
+use std::borrow::Cow;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JsonPatchOp {
+    Add,
+    Remove,
+    Replace,
+    // Not part of RFC 6902: the keys of an object are in a different order. JSON objects are
+    // unordered, so this is only reported on request and never included in a JSON Patch.
+    Reorder,
+    // Move,
+    // Copy,
+    // Test,
+}
+
+impl fmt::Display for JsonPatchOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JsonPatchOp::Add => write!(f, "add"),
+            JsonPatchOp::Remove => write!(f, "remove"),
+            JsonPatchOp::Replace => write!(f, "replace"),
+            JsonPatchOp::Reorder => write!(f, "reorder"),
+            // JsonPatchOp::Move => write!(f, "move"),
+            // JsonPatchOp::Copy => write!(f, "copy"),
+            // JsonPatchOp::Test => write!(f, "test"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CompareOptions {
+    // By default numbers are compared by their (arbitrary precision) value.
+    pub exact_numbers: bool,
+    pub report_reordering: bool,
+    // Compare large objects and arrays on rayon's thread pool.
+    pub parallel: bool,
+    // Compare strings that contain JSON objects or arrays by their JSON, where they match one of
+    // these paths (see `path::matches`), or anywhere if there are none.
+    pub embedded_json: Option<Vec<String>>,
+    // Normalize values before they are compared.
+    pub normalize: Vec<normalize::Rule>,
+    // Treat object members that are null, or empty arrays or objects, as if they were missing.
+    pub null_equals_missing: bool,
+    pub empty_equals_missing: bool,
+}
+
+impl CompareOptions {
+    // Whether an object member with this value is treated as if it were missing.
+    pub(crate) fn like_missing(&self, value: &serde_json::Value) -> bool {
+        match value {
+            serde_json::Value::Null => self.null_equals_missing,
+            serde_json::Value::Array(vec) => self.empty_equals_missing && vec.is_empty(),
+            serde_json::Value::Object(map) => self.empty_equals_missing && map.is_empty(),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Patch {
+    pub op: JsonPatchOp,
+    pub path: String,
+    pub value: Option<serde_json::Value>,
+    pub old_value: Option<serde_json::Value>,
+}
+
+// Only the tests compare with the default options; `main` always builds them from `Args`.
+#[cfg(test)]
+fn compare(left: &serde_json::Value, right: &serde_json::Value, patches: &mut Vec<Patch>) {
+    compare_with(left, right, patches, &CompareOptions::default());
+}
+
+pub fn compare_with(
+    left: &serde_json::Value,
+    right: &serde_json::Value,
+    patches: &mut Vec<Patch>,
+    options: &CompareOptions,
+) {
+    if options.normalize.is_empty() {
+        compare_documents(left, right, patches, options);
+    } else {
+        normalize::compare_with(left, right, patches, options);
+    }
+}
+
+pub(crate) fn compare_documents(
+    left: &serde_json::Value,
+    right: &serde_json::Value,
+    patches: &mut Vec<Patch>,
+    options: &CompareOptions,
+) {
+    let hashes = hash::Hashes::new(left, right, options);
+    if !hashes.equal(left, right, options) {
+        compare_values(left, right, patches, "", options, &hashes);
+    }
+}
+
+// Compare two values that are known to differ, at `path` ("" for the whole document). Values are
+// borrowed until a change is found, and identical subtrees are recognized by their hashes
+// rather than walked.
+pub(crate) fn compare_values(
+    left: &serde_json::Value,
+    right: &serde_json::Value,
+    patches: &mut Vec<Patch>,
+    path: &str,
+    options: &CompareOptions,
+    hashes: &hash::Hashes,
+) {
+    if let Some((left, right)) = embedded_json(left, right, path, options) {
+        let hashes = hash::Hashes::new(&left, &right, options);
+        if !hashes.equal(&left, &right, options) {
+            let path = format!("{}{}", path, path::EMBEDDED);
+            compare_values(&left, &right, patches, &path, options, &hashes);
+        }
+        return;
+    }
+    match (left, right) {
+        (serde_json::Value::Object(left), serde_json::Value::Object(right)) => {
+            compare_objects(left, right, patches, path, options, hashes)
+        }
+        (serde_json::Value::Array(left), serde_json::Value::Array(right)) => {
+            compare_arrays(left, right, patches, path, options, hashes)
+        }
+        // `compare` reports a change to the whole document at "/".
+        _ => patches.push(Patch {
+            op: JsonPatchOp::Replace,
+            path: if path.is_empty() { "/" } else { path }.to_string(),
+            value: Some(right.clone()),
+            old_value: Some(left.clone()),
+        }),
+    }
+}
+
+// The JSON in two strings, if both are objects or both are arrays and the options ask for it.
+// Anything else is compared as a string.
+fn embedded_json(
+    left: &serde_json::Value,
+    right: &serde_json::Value,
+    path: &str,
+    options: &CompareOptions,
+) -> Option<(serde_json::Value, serde_json::Value)> {
+    let paths = options.embedded_json.as_ref()?;
+    let (serde_json::Value::String(left), serde_json::Value::String(right)) = (left, right) else {
+        return None;
+    };
+    if !paths.is_empty() && !paths.iter().any(|pattern| path::matches(pattern, path)) {
+        return None;
+    }
+    let parse = |text: &str| serde_json::from_str::<serde_json::Value>(text).ok();
+    match (parse(left)?, parse(right)?) {
+        (left @ serde_json::Value::Object(_), right @ serde_json::Value::Object(_))
+        | (left @ serde_json::Value::Array(_), right @ serde_json::Value::Array(_)) => {
+            Some((left, right))
+        }
+        _ => None,
+    }
+}
+
+// How many members of an object or elements of an array are compared in one parallel task.
+const CHUNK: usize = 256;
+
+// Call `compare` for each item. With `options.parallel`, long slices are split into chunks that
+// are compared concurrently, each into its own patches. The chunks' patches are concatenated in
+// order, so the result is the same however the chunks are scheduled.
+fn compare_each<T: Sync>(
+    items: &[T],
+    patches: &mut Vec<Patch>,
+    options: &CompareOptions,
+    compare: impl Fn(usize, &T, &mut Vec<Patch>) + Sync,
+) {
+    if options.parallel && items.len() > CHUNK {
+        let chunks: Vec<Vec<Patch>> = items
+            .par_chunks(CHUNK)
+            .enumerate()
+            .map(|(c, chunk)| {
+                let mut patches: Vec<Patch> = Vec::new();
+                for (j, item) in chunk.iter().enumerate() {
+                    compare(c * CHUNK + j, item, &mut patches);
+                }
+                patches
+            })
+            .collect();
+        patches.extend(chunks.into_iter().flatten());
+    } else {
+        for (i, item) in items.iter().enumerate() {
+            compare(i, item, patches);
+        }
+    }
+}
+
+fn compare_arrays(
+    left: &[serde_json::Value],
+    right: &[serde_json::Value],
+    patches: &mut Vec<Patch>,
+    path: &str,
+    options: &CompareOptions,
+    hashes: &hash::Hashes,
+) {
+    let common = left.len().min(right.len());
+    compare_each(&left[..common], patches, options, |i, old, patches| {
+        let new = &right[i];
+        if !hashes.equal(old, new, options) {
+            let path = format!("{}/{}", path, i);
+            compare_values(old, new, patches, &path, options, hashes);
+        }
+    });
+    // From the end, so that each index is still valid when the removals are applied in order.
+    for (i, old) in left.iter().enumerate().skip(right.len()).rev() {
+        patches.push(Patch {
+            op: JsonPatchOp::Remove,
+            path: format!("{}/{}", path, i),
+            value: None,
+            old_value: Some(old.clone()),
+        });
+    }
+    for (i, new) in right.iter().enumerate().skip(left.len()) {
+        patches.push(Patch {
+            op: JsonPatchOp::Add,
+            path: format!("{}/{}", path, i),
+            value: Some(new.clone()),
+            old_value: None,
+        });
+    }
+}
+
+fn compare_objects(
+    left: &serde_json::Map<String, serde_json::Value>,
+    right: &serde_json::Map<String, serde_json::Value>,
+    patches: &mut Vec<Patch>,
+    path: &str,
+    options: &CompareOptions,
+    hashes: &hash::Hashes,
+) {
+    if options.report_reordering {
+        let left_order: Vec<&String> = left.keys().filter(|k| right.contains_key(*k)).collect();
+        let right_order: Vec<&String> = right.keys().filter(|k| left.contains_key(*k)).collect();
+        if left_order != right_order {
+            patches.push(Patch {
+                op: JsonPatchOp::Reorder,
+                path: if path.is_empty() { "/" } else { path }.to_string(),
+                value: Some(serde_json::json!(right_order)),
+                old_value: Some(serde_json::json!(left_order)),
+            });
+        }
+    }
+    // Walk the keys in document order, so that changes are reported in the order people read
+    // them. Keys only on the right come last, in their order on the right.
+    let compare_member = |(key, old): &(&String, &serde_json::Value), patches: &mut Vec<Patch>| {
+        match right.get(*key) {
+            Some(new) if hashes.equal(old, new, options) => {}
+            Some(new) if options.like_missing(old) && options.like_missing(new) => {}
+            Some(new) => {
+                let path = format!("{}/{}", path, path::escape(key));
+                compare_values(old, new, patches, &path, options, hashes);
+            }
+            None if options.like_missing(old) => {}
+            None => patches.push(Patch {
+                op: JsonPatchOp::Remove,
+                path: format!("{}/{}", path, path::escape(key)),
+                value: None,
+                old_value: Some((*old).clone()),
+            }),
+        }
+    };
+    if options.parallel && left.len() > CHUNK {
+        let members: Vec<(&String, &serde_json::Value)> = left.iter().collect();
+        compare_each(&members, patches, options, |_, member, patches| {
+            compare_member(member, patches)
+        });
+    } else {
+        for member in left {
+            compare_member(&member, patches);
+        }
+    }
+    for (key, new) in right {
+        if !left.contains_key(key) && !options.like_missing(new) {
+            patches.push(Patch {
+                op: JsonPatchOp::Add,
+                path: format!("{}/{}", path, path::escape(key)),
+                value: Some(new.clone()),
+                old_value: None,
+            });
+        }
+    }
+}
+
+pub(crate) fn equal(
+    left: &serde_json::Value,
+    right: &serde_json::Value,
+    options: &CompareOptions,
+) -> bool {
+    match (left, right) {
+        (serde_json::Value::Number(l), serde_json::Value::Number(r)) if !options.exact_numbers => {
+            equal_numbers(l, r)
+        }
+        (serde_json::Value::Array(l), serde_json::Value::Array(r)) => equal_arrays(l, r, options),
+        (serde_json::Value::Object(l), serde_json::Value::Object(r)) => {
+            members(l, options).count() == members(r, options).count()
+                && (!options.report_reordering
+                    || members(l, options)
+                        .map(|(key, _)| key)
+                        .eq(members(r, options).map(|(key, _)| key)))
+                && members(l, options).all(|(key, value)| {
+                    r.get(key)
+                        .is_some_and(|r| !options.like_missing(r) && equal(value, r, options))
+                })
+        }
+        _ => left == right,
+    }
+}
+
+// The members of an object that aren't treated as missing.
+pub(crate) fn members<'a>(
+    map: &'a serde_json::Map<String, serde_json::Value>,
+    options: &'a CompareOptions,
+) -> impl Iterator<Item = (&'a String, &'a serde_json::Value)> {
+    map.iter().filter(|(_, value)| !options.like_missing(value))
+}
+
+fn equal_arrays(
+    left: &[serde_json::Value],
+    right: &[serde_json::Value],
+    options: &CompareOptions,
+) -> bool {
+    left.len() == right.len() && left.iter().zip(right).all(|(l, r)| equal(l, r, options))
+}
+
+fn equal_numbers(left: &serde_json::Number, right: &serde_json::Number) -> bool {
+    match (
+        normalize_number(left.as_str()),
+        normalize_number(right.as_str()),
+    ) {
+        (Some(l), Some(r)) => l == r,
+        _ => left == right,
+    }
+}
+
+// Reduce a JSON number to (negative, significant digits, exponent) so that numbers can be
+// compared by value without going through f64, e.g. "1.50" and "15e-1" both become
+// (false, "15", -1). Zero is always (false, "", 0). The digits are only copied when the number
+// has a non-zero fraction.
+pub(crate) fn normalize_number(number: &str) -> Option<(bool, Cow<'_, str>, i64)> {
+    let (negative, unsigned) = match number.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned),
+        None => (false, number),
+    };
+    let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+        Some(i) => (&unsigned[..i], unsigned[i + 1..].parse::<i64>().ok()?),
+        None => (unsigned, 0),
+    };
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    // A fraction of zeros only adds trailing zeros, which are trimmed again below.
+    let (digits, shift) = if fraction.bytes().all(|b| b == b'0') {
+        (Cow::Borrowed(integer), 0)
+    } else {
+        (
+            Cow::Owned(format!("{}{}", integer, fraction)),
+            fraction.len(),
+        )
+    };
+    let leading = digits.len() - digits.trim_start_matches('0').len();
+    let significant = digits.trim_matches('0').len();
+    if significant == 0 {
+        return Some((false, Cow::Borrowed(""), 0));
+    }
+    let trailing = digits.len() - leading - significant;
+    let exponent = exponent
+        .checked_sub(i64::try_from(shift).ok()?)?
+        .checked_add(i64::try_from(trailing).ok()?)?;
+    let digits = match digits {
+        Cow::Borrowed(digits) => Cow::Borrowed(&digits[leading..leading + significant]),
+        Cow::Owned(digits) => Cow::Owned(digits[leading..leading + significant].to_string()),
+    };
+    Some((negative, digits, exponent))
+}
+
+// Changes inside embedded JSON have no JSON Pointer, so they can't be written as JSON Patch.
+pub fn json_patch_operation(patch: &Patch) -> Result<serde_json::Value, String> {
+    if !path::is_pointer(&patch.path) {
+        return Err(format!(
+            "{} is not a JSON Pointer (a change inside embedded JSON?)",
+            patch.path
+        ));
+    }
+    if patch.op == JsonPatchOp::Remove {
+        Ok(serde_json::json!({"op": "remove", "path": patch.path}))
+    } else {
+        Ok(
+            serde_json::json!({"op": patch.op.to_string(), "path": patch.path, "value": patch.value}),
+        )
+    }
+}
+
+// RFC 6902 ignores members it doesn't define, so an operation with its old value is still valid
+// JSON Patch, and can be inverted.
+pub fn extended_json_patch_operation(patch: &Patch) -> Result<serde_json::Value, String> {
+    let mut ret = json_patch_operation(patch)?;
+    if let Some(old_value) = &patch.old_value {
+        ret["old_value"] = old_value.clone();
+    }
+    Ok(ret)
+}
+
+pub fn generate_json_patch(patches: &Vec<Patch>) -> Result<Vec<String>, String> {
+    let mut ret: Vec<String> = Vec::new();
+    for patch in patches {
+        if patch.op == JsonPatchOp::Reorder {
+            continue;
+        }
+        ret.push(json_patch_operation(patch)?.to_string());
+    }
+    Ok(ret)
+}
+
+pub fn generate_extended_json_patch(patches: &[Patch]) -> Result<Vec<String>, String> {
+    patches
+        .iter()
+        .filter(|patch| patch.op != JsonPatchOp::Reorder)
+        .map(|patch| Ok(extended_json_patch_operation(patch)?.to_string()))
+        .collect()
+}
+
+// Reads a JSON Patch, with old values if it is extended. Only the operations `compare` produces
+// are supported.
+pub fn parse_json_patch(json_patch: &serde_json::Value) -> Result<Vec<Patch>, String> {
+    let operations = json_patch
+        .as_array()
+        .ok_or("a JSON Patch must be an array")?;
+    let mut ret: Vec<Patch> = Vec::new();
+    for (i, operation) in operations.iter().enumerate() {
+        let member = |name: &str| operation.get(name).cloned();
+        let op = match operation.get("op").and_then(|op| op.as_str()) {
+            Some("add") => JsonPatchOp::Add,
+            Some("remove") => JsonPatchOp::Remove,
+            Some("replace") => JsonPatchOp::Replace,
+            Some(op) => return Err(format!("unsupported operation {} at index {}", op, i)),
+            None => return Err(format!("missing op at index {}", i)),
+        };
+        let path = match operation.get("path").and_then(|path| path.as_str()) {
+            // `compare` reports a change to the whole document at "/".
+            Some("") => "/".to_string(),
+            Some(path) if path::is_pointer(path) => path.to_string(),
+            Some(path) => return Err(format!("invalid path {} at index {}", path, i)),
+            None => return Err(format!("missing path at index {}", i)),
+        };
+        let value = member("value");
+        if value.is_none() && op != JsonPatchOp::Remove {
+            return Err(format!("missing value at index {}", i));
+        }
+        ret.push(Patch {
+            op,
+            path,
+            value,
+            old_value: member("old_value"),
+        });
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+// The float fixtures are deliberately close to PI and E, and `vec!` keeps the expectations uniform.
+#[allow(clippy::approx_constant, clippy::useless_vec)]
+mod tests {
+    use crate::{
+        compare, compare_with, generate_extended_json_patch, generate_json_patch, parse_json_patch,
+        CompareOptions, JsonPatchOp, Patch,
+    };
+
+    #[test]
+    fn test_compare_array_nop() {
+        let doc = serde_json::json!(["a", "b"]);
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&doc, &doc.clone(), &mut patches);
+        assert!(patches.is_empty());
+    }
+
+    #[test]
+    fn test_compare_array_added_values() {
+        let left = serde_json::json!(["a", "b"]);
+        let right = serde_json::json!(["a", "b", "c", "d"]);
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&left, &right.clone(), &mut patches);
+        let expected = vec![
+            Patch {
+                op: JsonPatchOp::Add,
+                path: "/2".to_string(),
+                value: Some(serde_json::json!("c")),
+                old_value: None,
+            },
+            Patch {
+                op: JsonPatchOp::Add,
+                path: "/3".to_string(),
+                value: Some(serde_json::json!("d")),
+                old_value: None,
+            },
+        ];
+        assert_eq!(expected, patches);
+    }
+
+    #[test]
+    fn test_compare_array_changed_value() {
+        let left = serde_json::json!(["a", "b", "c"]);
+        let right = serde_json::json!(["a", 2, "c"]);
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&left, &right.clone(), &mut patches);
+        let expected = vec![Patch {
+            op: JsonPatchOp::Replace,
+            path: "/1".to_string(),
+            value: Some(serde_json::json!(2)),
+            old_value: Some(serde_json::json!("b")),
+        }];
+        assert_eq!(expected, patches);
+    }
+
+    #[test]
+    fn test_compare_array_changed_nested_value() {
+        let left = serde_json::json!(["a", ["b", "c"], "d"]);
+        let right = serde_json::json!(["a", ["b", 3], "d"]);
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&left, &right.clone(), &mut patches);
+        let expected = vec![Patch {
+            op: JsonPatchOp::Replace,
+            path: "/1/1".to_string(),
+            value: Some(serde_json::json!(3)),
+            old_value: Some(serde_json::json!("c")),
+        }];
+        assert_eq!(expected, patches);
+    }
+
+    #[test]
+    fn test_compare_array_removed_value() {
+        let left = serde_json::json!(["a", "b", "c", "d"]);
+        let right = serde_json::json!(["a", "b"]);
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&left, &right.clone(), &mut patches);
+        let expected = vec![
+            Patch {
+                op: JsonPatchOp::Remove,
+                path: "/3".to_string(),
+                value: None,
+                old_value: Some(serde_json::json!("d")),
+            },
+            Patch {
+                op: JsonPatchOp::Remove,
+                path: "/2".to_string(),
+                value: None,
+                old_value: Some(serde_json::json!("c")),
+            },
+        ];
+        assert_eq!(expected, patches);
+    }
+
+    #[test]
+    fn test_compare_object_nop() {
+        let doc = serde_json::json!({
+            "string": "This is a string.",
+            "integer": 42,
+            "float": 3.14159,
+            "object": {
+                "substring": "This is another string."
+            },
+            "array": ["one", "two"],
+            "boolean": true,
+            "null": null
+        });
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&doc, &doc.clone(), &mut patches);
+        assert!(patches.is_empty());
+    }
+
+    #[test]
+    fn test_compare_object_added() {
+        let left = serde_json::json!({});
+        let right = serde_json::json!({
+            "string": "This is a string.",
+            "integer": 42,
+            "float": 3.14159,
+            "object": {
+                "substring": "This is another string."
+            },
+            "array": ["one", "two"],
+            "boolean": true,
+            "null": null
+        });
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&left, &right, &mut patches);
+        let expected = vec![
+            Patch {
+                op: JsonPatchOp::Add,
+                path: "/string".to_string(),
+                value: Some(serde_json::json!("This is a string.")),
+                old_value: None,
+            },
+            Patch {
+                op: JsonPatchOp::Add,
+                path: "/integer".to_string(),
+                value: Some(serde_json::json!(42)),
+                old_value: None,
+            },
+            Patch {
+                op: JsonPatchOp::Add,
+                path: "/float".to_string(),
+                value: Some(serde_json::json!(3.14159)),
+                old_value: None,
+            },
+            Patch {
+                op: JsonPatchOp::Add,
+                path: "/object".to_string(),
+                value: Some(serde_json::json!({"substring": "This is another string."})),
+                old_value: None,
+            },
+            Patch {
+                op: JsonPatchOp::Add,
+                path: "/array".to_string(),
+                value: Some(serde_json::json!(["one", "two"])),
+                old_value: None,
+            },
+            Patch {
+                op: JsonPatchOp::Add,
+                path: "/boolean".to_string(),
+                value: Some(serde_json::json!(true)),
+                old_value: None,
+            },
+            Patch {
+                op: JsonPatchOp::Add,
+                path: "/null".to_string(),
+                value: Some(serde_json::json!(null)),
+                old_value: None,
+            },
+        ];
+        assert_eq!(expected.len(), patches.len());
+        assert!(expected.iter().all(|item| patches.contains(item)));
+    }
+
+    #[test]
+    fn test_compare_object_removed() {
+        let left = serde_json::json!({
+            "string": "This is a string.",
+            "integer": 42,
+            "float": 3.14159,
+            "object": {
+                "substring": "This is another string."
+            },
+            "array": ["one", "two"],
+            "boolean": true,
+            "null": null
+        });
+        let right = serde_json::json!({});
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&left, &right, &mut patches);
+        let expected = vec![
+            Patch {
+                op: JsonPatchOp::Remove,
+                path: "/string".to_string(),
+                value: None,
+                old_value: Some(serde_json::json!("This is a string.")),
+            },
+            Patch {
+                op: JsonPatchOp::Remove,
+                path: "/integer".to_string(),
+                value: None,
+                old_value: Some(serde_json::json!(42)),
+            },
+            Patch {
+                op: JsonPatchOp::Remove,
+                path: "/float".to_string(),
+                value: None,
+                old_value: Some(serde_json::json!(3.14159)),
+            },
+            Patch {
+                op: JsonPatchOp::Remove,
+                path: "/object".to_string(),
+                value: None,
+                old_value: Some(serde_json::json!({"substring": "This is another string."})),
+            },
+            Patch {
+                op: JsonPatchOp::Remove,
+                path: "/array".to_string(),
+                value: None,
+                old_value: Some(serde_json::json!(["one", "two"])),
+            },
+            Patch {
+                op: JsonPatchOp::Remove,
+                path: "/boolean".to_string(),
+                value: None,
+                old_value: Some(serde_json::json!(true)),
+            },
+            Patch {
+                op: JsonPatchOp::Remove,
+                path: "/null".to_string(),
+                value: None,
+                old_value: Some(serde_json::json!(null)),
+            },
+        ];
+        assert_eq!(expected.len(), patches.len());
+        assert!(expected.iter().all(|item| patches.contains(item)));
+    }
+
+    #[test]
+    fn test_compare_object_replaced() {
+        let left = serde_json::json!({
+            "string": "There are strange things done in the midnight sun",
+            "integer": 42,
+            "float": 3.14159,
+            "object": {
+                "substring": "By the men who moil for gold;"
+            },
+            "array": ["one", "two"],
+            "boolean": true,
+            "null": null
+        });
+        let right = serde_json::json!({
+            "string": "The Arctic trails have their secret tales",
+            "integer": 60606,
+            "float": 2.71828,
+            "object": {
+                "substring": "That would make your blood run cold;"
+            },
+            "array": ["a", "b"],
+            "boolean": false,
+            "null": "NOT NULL"
+        });
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&left, &right, &mut patches);
+        let expected = vec![
+            Patch {
+                op: JsonPatchOp::Replace,
+                path: "/string".to_string(),
+                value: Some(serde_json::json!(
+                    "The Arctic trails have their secret tales"
+                )),
+                old_value: Some(serde_json::json!(
+                    "There are strange things done in the midnight sun"
+                )),
+            },
+            Patch {
+                op: JsonPatchOp::Replace,
+                path: "/integer".to_string(),
+                value: Some(serde_json::json!(60606)),
+                old_value: Some(serde_json::json!(42)),
+            },
+            Patch {
+                op: JsonPatchOp::Replace,
+                path: "/float".to_string(),
+                value: Some(serde_json::json!(2.71828)),
+                old_value: Some(serde_json::json!(3.14159)),
+            },
+            Patch {
+                op: JsonPatchOp::Replace,
+                path: "/object/substring".to_string(),
+                value: Some(serde_json::json!("That would make your blood run cold;")),
+                old_value: Some(serde_json::json!("By the men who moil for gold;")),
+            },
+            Patch {
+                op: JsonPatchOp::Replace,
+                path: "/array/0".to_string(),
+                value: Some(serde_json::json!("a")),
+                old_value: Some(serde_json::json!("one")),
+            },
+            Patch {
+                op: JsonPatchOp::Replace,
+                path: "/array/1".to_string(),
+                value: Some(serde_json::json!("b")),
+                old_value: Some(serde_json::json!("two")),
+            },
+            Patch {
+                op: JsonPatchOp::Replace,
+                path: "/boolean".to_string(),
+                value: Some(serde_json::json!(false)),
+                old_value: Some(serde_json::json!(true)),
+            },
+            Patch {
+                op: JsonPatchOp::Replace,
+                path: "/null".to_string(),
+                value: Some(serde_json::json!("NOT NULL")),
+                old_value: Some(serde_json::json!(null)),
+            },
+        ];
+        assert_eq!(expected.len(), patches.len());
+        assert!(expected.iter().all(|item| patches.contains(item)));
+    }
+
+    #[test]
+    fn test_compare_object_document_order() {
+        let left = serde_json::json!({"b": 1, "removed": 2, "a": 3});
+        let right = serde_json::json!({"added": 4, "a": 5, "b": 6});
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&left, &right, &mut patches);
+        let paths: Vec<&str> = patches.iter().map(|patch| patch.path.as_str()).collect();
+        assert_eq!(vec!["/b", "/removed", "/a", "/added"], paths);
+    }
+
+    #[test]
+    fn test_compare_object_escaped_keys() {
+        let left = serde_json::json!({"a/b": {"c~d": 1}});
+        let right = serde_json::json!({"a/b": {"c~d": 2}});
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&left, &right, &mut patches);
+        let expected = vec![Patch {
+            op: JsonPatchOp::Replace,
+            path: "/a~1b/c~0d".to_string(),
+            value: Some(serde_json::json!(2)),
+            old_value: Some(serde_json::json!(1)),
+        }];
+        assert_eq!(expected, patches);
+    }
+
+    #[test]
+    fn test_compare_object_reordered() {
+        let left = serde_json::json!({"a": 1, "object": {"b": 2, "c": 3}});
+        let right = serde_json::json!({"object": {"c": 3, "b": 2}, "a": 1});
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&left, &right, &mut patches);
+        assert!(patches.is_empty());
+        let options = CompareOptions {
+            report_reordering: true,
+            ..Default::default()
+        };
+        compare_with(&left, &right, &mut patches, &options);
+        let expected = vec![
+            Patch {
+                op: JsonPatchOp::Reorder,
+                path: "/".to_string(),
+                value: Some(serde_json::json!(["object", "a"])),
+                old_value: Some(serde_json::json!(["a", "object"])),
+            },
+            Patch {
+                op: JsonPatchOp::Reorder,
+                path: "/object".to_string(),
+                value: Some(serde_json::json!(["c", "b"])),
+                old_value: Some(serde_json::json!(["b", "c"])),
+            },
+        ];
+        assert_eq!(expected, patches);
+        assert!(generate_json_patch(&patches).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_compare_string_nop() {
+        let doc = serde_json::json!("There are strange things done in the midnight sun");
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&doc, &doc.clone(), &mut patches);
+        assert!(patches.is_empty());
+    }
+
+    #[test]
+    fn test_compare_string_changed_value() {
+        let left = serde_json::json!("There are strange things done in the midnight sun");
+        let right = serde_json::json!("By the men who moil for gold;");
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&left, &right.clone(), &mut patches);
+        let expected = vec![Patch {
+            op: JsonPatchOp::Replace,
+            path: "/".to_string(),
+            value: Some(serde_json::json!("By the men who moil for gold;")),
+            old_value: Some(serde_json::json!(
+                "There are strange things done in the midnight sun"
+            )),
+        }];
+        assert_eq!(expected, patches);
+    }
+
+    #[test]
+    fn test_compare_string_changed_type() {
+        let left = serde_json::json!("There are strange things done in the midnight sun");
+        let right = serde_json::json!(42);
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&left, &right.clone(), &mut patches);
+        let expected = vec![Patch {
+            op: JsonPatchOp::Replace,
+            path: "/".to_string(),
+            value: Some(serde_json::json!(42)),
+            old_value: Some(serde_json::json!(
+                "There are strange things done in the midnight sun"
+            )),
+        }];
+        assert_eq!(expected, patches);
+    }
+
+    #[test]
+    fn test_compare_number_numeric() {
+        let left: serde_json::Value =
+            serde_json::from_str(r#"[1.0, 12345678901234567890123, 1.5e2, -0]"#).unwrap();
+        let right: serde_json::Value =
+            serde_json::from_str(r#"[1.00, 12345678901234567890123, 150, 0.0]"#).unwrap();
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&left, &right, &mut patches);
+        assert!(patches.is_empty());
+    }
+
+    #[test]
+    fn test_compare_number_numeric_large_integer() {
+        let left: serde_json::Value = serde_json::from_str("12345678901234567890123").unwrap();
+        let right: serde_json::Value = serde_json::from_str("12345678901234567890124").unwrap();
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&left, &right, &mut patches);
+        let expected = vec![Patch {
+            op: JsonPatchOp::Replace,
+            path: "/".to_string(),
+            value: Some(right.clone()),
+            old_value: Some(left.clone()),
+        }];
+        assert_eq!(expected, patches);
+        assert_eq!(
+            vec![r#"{"op":"replace","path":"/","value":12345678901234567890124}"#],
+            generate_json_patch(&patches).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_compare_number_exact() {
+        let left: serde_json::Value = serde_json::from_str(r#"{"price": 1.0}"#).unwrap();
+        let right: serde_json::Value = serde_json::from_str(r#"{"price": 1.00}"#).unwrap();
+        let mut patches: Vec<Patch> = Vec::new();
+        let options = CompareOptions {
+            exact_numbers: true,
+            ..Default::default()
+        };
+        compare_with(&left, &right, &mut patches, &options);
+        let expected = vec![Patch {
+            op: JsonPatchOp::Replace,
+            path: "/price".to_string(),
+            value: Some(right["price"].clone()),
+            old_value: Some(left["price"].clone()),
+        }];
+        assert_eq!(expected, patches);
+        assert_eq!(
+            vec![r#"{"op":"replace","path":"/price","value":1.00}"#],
+            generate_json_patch(&patches).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_compare_parallel() {
+        let document = |offset: usize| {
+            let wide: serde_json::Map<String, serde_json::Value> = (offset..1000 + offset)
+                .map(|i| (format!("key{}", i), serde_json::json!((i + offset) % 7)))
+                .collect();
+            let long: Vec<serde_json::Value> = (0..1000 + offset)
+                .map(|i| serde_json::json!({"id": i, "value": [i % (3 + offset)]}))
+                .collect();
+            serde_json::json!({"wide": wide, "long": long})
+        };
+        let (left, right) = (document(0), document(10));
+        let mut expected: Vec<Patch> = Vec::new();
+        compare(&left, &right, &mut expected);
+        assert!(expected.len() > 1000);
+        let mut patches: Vec<Patch> = Vec::new();
+        let options = CompareOptions {
+            parallel: true,
+            ..Default::default()
+        };
+        compare_with(&left, &right, &mut patches, &options);
+        assert_eq!(expected, patches);
+    }
+
+    #[test]
+    fn test_compare_object_missing() {
+        let paths = |left, right, options: &CompareOptions| -> Vec<String> {
+            let mut patches: Vec<Patch> = Vec::new();
+            compare_with(&left, &right, &mut patches, options);
+            patches.into_iter().map(|patch| patch.path).collect()
+        };
+        let left = serde_json::json!({"a": null, "b": [], "c": {}, "d": {"e": null, "f": 1}});
+        let right = serde_json::json!({"d": {"f": 1, "g": []}, "h": null});
+        let cases = [
+            (false, false, vec!["/a", "/b", "/c", "/d/e", "/d/g", "/h"]),
+            (true, false, vec!["/b", "/c", "/d/g"]),
+            (false, true, vec!["/a", "/d/e", "/h"]),
+            (true, true, vec![]),
+        ];
+        for (null_equals_missing, empty_equals_missing, expected) in cases {
+            let options = CompareOptions {
+                null_equals_missing,
+                empty_equals_missing,
+                ..Default::default()
+            };
+            assert_eq!(expected, paths(left.clone(), right.clone(), &options));
+        }
+        // A null member is still replaced by a value.
+        let options = CompareOptions {
+            null_equals_missing: true,
+            empty_equals_missing: true,
+            ..Default::default()
+        };
+        let left = serde_json::json!({"a": null, "b": 0});
+        let right = serde_json::json!({"a": [], "b": null});
+        assert_eq!(vec!["/b"], paths(left, right, &options));
+    }
+
+    #[test]
+    fn test_compare_embedded_json() {
+        let left = serde_json::json!({
+            "body": "{\"a\": 1, \"b\": [1]}",
+            "policy": "{\"Statement\": []}",
+            "same": "{\"a\": 1}",
+            "text": "[1]"
+        });
+        let right = serde_json::json!({
+            "body": "{\"b\":[1,2],\"a\":1}",
+            "policy": "{\"Statement\": [{\"Effect\": \"Allow\"}]}",
+            "same": "{ \"a\" : 1 }",
+            "text": "plain"
+        });
+        let options = CompareOptions {
+            embedded_json: Some(vec!["/body".to_string(), "/same".to_string()]),
+            ..Default::default()
+        };
+        let mut patches: Vec<Patch> = Vec::new();
+        compare_with(&left, &right, &mut patches, &options);
+        let paths: Vec<&str> = patches.iter().map(|patch| patch.path.as_str()).collect();
+        assert_eq!(vec!["/body/~j/b/1", "/policy", "/text"], paths);
+        assert_eq!(Some(serde_json::json!(2)), patches[0].value);
+        // Anywhere, without paths.
+        let options = CompareOptions {
+            embedded_json: Some(Vec::new()),
+            ..Default::default()
+        };
+        let mut patches: Vec<Patch> = Vec::new();
+        compare_with(&left, &right, &mut patches, &options);
+        let paths: Vec<&str> = patches.iter().map(|patch| patch.path.as_str()).collect();
+        assert_eq!(
+            vec!["/body/~j/b/1", "/policy/~j/Statement/0", "/text"],
+            paths
+        );
+        // Changes inside embedded JSON have no JSON Pointer.
+        assert_eq!(
+            Err("/body/~j/b/1 is not a JSON Pointer (a change inside embedded JSON?)".to_string()),
+            generate_json_patch(&patches)
+        );
+        assert!(generate_extended_json_patch(&patches).is_err());
+    }
+
+    #[test]
+    fn test_parse_json_patch() {
+        let left = serde_json::json!({"a": [1, 2, 3], "b": {"c": 1}});
+        let right = serde_json::json!({"a": [1], "b": {"c": 2}, "d": null});
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&left, &right, &mut patches);
+        let json_patch = format!(
+            "[{}]",
+            generate_extended_json_patch(&patches).unwrap().join(",")
+        );
+        let json_patch: serde_json::Value = serde_json::from_str(&json_patch).unwrap();
+        assert_eq!(Ok(patches), parse_json_patch(&json_patch));
+        let cases = [
+            (serde_json::json!({}), "a JSON Patch must be an array"),
+            (
+                serde_json::json!([{"op": "move", "from": "/a", "path": "/b"}]),
+                "unsupported operation move at index 0",
+            ),
+            (
+                serde_json::json!([{"op": "add", "path": "/a"}]),
+                "missing value at index 0",
+            ),
+            (
+                serde_json::json!([{"op": "remove", "path": "/a/~j/b"}]),
+                "invalid path /a/~j/b at index 0",
+            ),
+        ];
+        for (json_patch, message) in cases {
+            assert_eq!(Err(message.to_string()), parse_json_patch(&json_patch));
+        }
+    }
+}
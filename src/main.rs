@@ -1,586 +1,1311 @@
-use clap::Parser;
-use std::collections::HashSet;
-use std::fmt;
+use clap::{Parser, Subcommand};
+use jsondiff::format::{self, strings::StringDiff, Format};
+use jsondiff::{
+    compare_with, compose, dir, extended_json_patch_operation, generate_extended_json_patch,
+    generate_json_patch, invert, json_patch_operation, merge, normalize, parse_json_patch, parser,
+    path, rebase, stat, stream, CompareOptions, JsonPatchOp, Patch,
+};
 use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::process;
 
-#[derive(Debug, PartialEq)]
-enum JsonPatchOp {
-    Add,
-    Remove,
-    Replace,
-    // Move,
-    // Copy,
-    // Test,
-}
-
-impl fmt::Display for JsonPatchOp {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            JsonPatchOp::Add => write!(f, "add"),
-            JsonPatchOp::Remove => write!(f, "remove"),
-            JsonPatchOp::Replace => write!(f, "replace"),
-            // JsonPatchOp::Move => write!(f, "move"),
-            // JsonPatchOp::Copy => write!(f, "copy"),
-            // JsonPatchOp::Test => write!(f, "test"),
-        }
-    }
-}
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
 
+// Comparing two files needs no subcommand, so that `jsondiff a.json b.json` keeps working.
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    args: Option<Args>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Merge the changes two files made to a common base
+    Merge(MergeArgs),
+    /// Merge as a git merge driver, writing the result over OURS
+    GitMergeDriver(GitMergeDriverArgs),
+    /// Compare as an external diff for git (GIT_EXTERNAL_DIFF or a diff driver)
+    GitDiff(GitDiffArgs),
+    /// Invert a JSON Patch with old values (see --old-values), to undo it
+    Invert(InvertArgs),
+    /// Combine JSON Patches, applied in order, into a single patch
+    Compose(ComposeArgs),
+    /// Rebase a JSON Patch made against OLD so that it applies to NEW
+    Rebase(RebaseArgs),
+}
+
+#[derive(clap::Args)]
 struct Args {
+    /// A file, or a directory to compare recursively with another directory
     file1: String,
     file2: String,
+    /// Compare numbers by their textual representation, so that e.g. 1.0 and 1.00 differ
+    #[arg(long)]
+    exact_numbers: bool,
+    /// Fail on duplicate object keys instead of warning about them
+    #[arg(long)]
+    strict: bool,
+    /// Report objects whose keys are in a different order (not included in JSON Patch output)
+    #[arg(long)]
+    report_reordering: bool,
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Patch)]
+    format: Format,
+    /// How to write the paths of changes (JSON Patch output always uses JSON Pointers)
+    #[arg(long, value_enum, default_value_t = path::PathStyle::Pointer)]
+    path_style: path::PathStyle,
+    /// Summarize the changes instead of listing them, by top-level key (default) or by depth
+    #[arg(long, value_enum, value_name = "BY", num_args = 0..=1, require_equals = true, default_missing_value = "key")]
+    stat: Option<stat::Group>,
+    /// When comparing directories, only compare files matching this glob (may be repeated)
+    #[arg(long)]
+    include: Vec<String>,
+    /// When comparing directories, skip files matching this glob (may be repeated)
+    #[arg(long)]
+    exclude: Vec<String>,
+    /// Compare the files while reading them, for documents too large to fit in memory (JSON
+    /// Patch output only)
+    #[arg(long, conflicts_with_all = ["report_reordering", "stat", "normalize"])]
+    stream: bool,
+    /// Compare large objects and arrays on N threads (0 for one per CPU)
+    #[arg(long, short, value_name = "N", default_value_t = 1)]
+    jobs: usize,
+    /// Include the old value of each replaced or removed value in JSON Patch output, so that the
+    /// patch can be inverted
+    #[arg(long)]
+    old_values: bool,
+    /// Show changes within replaced strings in text and HTML output, by word (default) or by
+    /// character
+    #[arg(long, value_enum, value_name = "BY", num_args = 0..=1, require_equals = true, default_missing_value = "word")]
+    string_diff: Option<StringDiff>,
+    /// Compare strings containing JSON objects or arrays by their JSON, in reports (JSON Patch
+    /// output still replaces the whole string). Only at PATH if given (may be repeated); `*`
+    /// matches any key or index
+    #[arg(long, value_name = "PATH", num_args = 0..=1, require_equals = true, value_parser = path::parse_pattern)]
+    parse_embedded_json: Option<Vec<String>>,
+    /// Normalize values before comparing them, everywhere or only at PATH and below (may be
+    /// repeated): trim, case, trailing-slash, timestamps, booleans or numbers
+    #[arg(long, value_name = "RULE[:PATH]")]
+    normalize: Vec<normalize::Rule>,
+    /// Treat object members that are null as if they were missing
+    #[arg(long)]
+    null_equals_missing: bool,
+    /// Treat object members that are empty arrays or objects as if they were missing
+    #[arg(long)]
+    empty_equals_missing: bool,
 }
 
-#[derive(Debug, PartialEq)]
-struct Patch {
-    op: JsonPatchOp,
-    path: String,
-    value: Option<serde_json::Value>,
-    old_value: Option<serde_json::Value>,
+#[derive(clap::Args)]
+struct MergeArgs {
+    base: String,
+    ours: String,
+    theirs: String,
+    /// Write the merged document to FILE instead of standard output
+    #[arg(long, short, value_name = "FILE")]
+    output: Option<String>,
+    /// Output format
+    #[arg(long, value_enum, default_value_t = merge::Format::Document)]
+    format: merge::Format,
+    /// Compare numbers by their textual representation, so that e.g. 1.0 and 1.00 differ
+    #[arg(long)]
+    exact_numbers: bool,
+    /// Fail on duplicate object keys instead of warning about them
+    #[arg(long)]
+    strict: bool,
 }
 
-fn compare(left: &serde_json::Value, right: &serde_json::Value, patches: &mut Vec<Patch>) {
-    if left.is_object() && right.is_object() {
-        compare_objects(
-            left.as_object().unwrap(),
-            right.as_object().unwrap(),
-            patches,
-            None,
-        );
-    } else if left.is_array() && right.is_array() {
-        compare_arrays(
-            left.as_array().unwrap(),
-            right.as_array().unwrap(),
-            patches,
-            None,
-        );
-    } else if left != right {
-        patches.push(Patch {
-            op: JsonPatchOp::Replace,
-            path: "/".to_string(),
-            value: Some(right.clone()),
-            old_value: Some(left.clone()),
-        });
+// Configured in git as `jsondiff git-merge-driver %O %A %B %P`.
+#[derive(clap::Args)]
+struct GitMergeDriverArgs {
+    base: String,
+    ours: String,
+    theirs: String,
+    /// The name of the file being merged, for messages
+    path: Option<String>,
+    /// Compare numbers by their textual representation, so that e.g. 1.0 and 1.00 differ
+    #[arg(long)]
+    exact_numbers: bool,
+}
+
+#[derive(clap::Args)]
+struct GitDiffArgs {
+    /// The arguments git passes: PATH OLD-FILE OLD-HEX OLD-MODE NEW-FILE NEW-HEX NEW-MODE, then
+    /// NEW-PATH and a description of the rename for renamed files, or only PATH for unmerged files
+    #[arg(required = true, num_args = 1..=9, value_name = "ARGS")]
+    git: Vec<String>,
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    /// How to write the paths of changes (JSON Patch output always uses JSON Pointers)
+    #[arg(long, value_enum, default_value_t = path::PathStyle::Pointer)]
+    path_style: path::PathStyle,
+    /// Compare numbers by their textual representation, so that e.g. 1.0 and 1.00 differ
+    #[arg(long)]
+    exact_numbers: bool,
+    /// Report objects whose keys are in a different order (not included in JSON Patch output)
+    #[arg(long)]
+    report_reordering: bool,
+    /// Show changes within replaced strings in text and HTML output, by word (default) or by
+    /// character
+    #[arg(long, value_enum, value_name = "BY", num_args = 0..=1, require_equals = true, default_missing_value = "word")]
+    string_diff: Option<StringDiff>,
+    /// Compare strings containing JSON objects or arrays by their JSON, in reports (JSON Patch
+    /// output still replaces the whole string). Only at PATH if given (may be repeated); `*`
+    /// matches any key or index
+    #[arg(long, value_name = "PATH", num_args = 0..=1, require_equals = true, value_parser = path::parse_pattern)]
+    parse_embedded_json: Option<Vec<String>>,
+    /// Normalize values before comparing them, everywhere or only at PATH and below (may be
+    /// repeated): trim, case, trailing-slash, timestamps, booleans or numbers
+    #[arg(long, value_name = "RULE[:PATH]")]
+    normalize: Vec<normalize::Rule>,
+    /// Treat object members that are null as if they were missing
+    #[arg(long)]
+    null_equals_missing: bool,
+    /// Treat object members that are empty arrays or objects as if they were missing
+    #[arg(long)]
+    empty_equals_missing: bool,
+}
+
+#[derive(clap::Args)]
+struct InvertArgs {
+    patch: String,
+    /// Write the inverted patch to FILE instead of standard output
+    #[arg(long, short, value_name = "FILE")]
+    output: Option<String>,
+}
+
+#[derive(clap::Args)]
+struct ComposeArgs {
+    #[arg(required = true)]
+    patches: Vec<String>,
+    /// Write the composed patch to FILE instead of standard output
+    #[arg(long, short, value_name = "FILE")]
+    output: Option<String>,
+}
+
+#[derive(clap::Args)]
+struct RebaseArgs {
+    patch: String,
+    /// The document the patch was made against
+    #[arg(required_unless_present = "onto", requires = "new")]
+    old: Option<String>,
+    /// The changed document
+    new: Option<String>,
+    /// Rebase onto the changes in this JSON Patch instead of comparing OLD and NEW
+    #[arg(long, value_name = "PATCH", conflicts_with_all = ["old", "new"])]
+    onto: Option<String>,
+    /// Write the rebased patch to FILE instead of standard output
+    #[arg(long, short, value_name = "FILE")]
+    output: Option<String>,
+}
+
+impl Args {
+    fn compare_options(&self) -> CompareOptions {
+        CompareOptions {
+            exact_numbers: self.exact_numbers,
+            report_reordering: self.report_reordering,
+            parallel: self.jobs != 1,
+            // A JSON Patch can't change part of a string.
+            embedded_json: if self.format == Format::Patch && self.stat.is_none() {
+                None
+            } else {
+                self.parse_embedded_json.clone()
+            },
+            normalize: self.normalize.clone(),
+            null_equals_missing: self.null_equals_missing,
+            empty_equals_missing: self.empty_equals_missing,
+        }
     }
 }
 
-// Clippy flags `for i in left.len()..right.len()` but misses the non-zero start. Allow for now.
-#[allow(clippy::needless_range_loop)]
-fn compare_arrays(
-    left: &Vec<serde_json::Value>,
-    right: &Vec<serde_json::Value>,
-    patches: &mut Vec<Patch>,
-    prefix: Option<&str>,
+// Detect gzip and zstd by their magic bytes rather than the file extension, so that compressed
+// snapshots can be compared regardless of how they are named. Contents are decompressed as they
+// are read rather than all at once.
+fn decompress<'a, R: BufRead + 'a>(mut reader: R) -> io::Result<Box<dyn BufRead + 'a>> {
+    let magic = reader.fill_buf()?;
+    if magic.starts_with(&GZIP_MAGIC) {
+        let decoder = flate2::bufread::MultiGzDecoder::new(reader);
+        Ok(Box::new(io::BufReader::new(decoder)))
+    } else if magic.starts_with(&ZSTD_MAGIC) {
+        let decoder = zstd::stream::read::Decoder::with_buffer(reader)?;
+        Ok(Box::new(io::BufReader::new(decoder)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+fn open_contents(file: &str) -> Box<dyn BufRead> {
+    try_open_contents(file).unwrap_or_else(|error| panic!("{}", error))
+}
+
+fn try_open_contents(file: &str) -> Result<Box<dyn BufRead>, String> {
+    let reader = match fs::File::open(file) {
+        Ok(file) => io::BufReader::new(file),
+        Err(error) => return Err(format!("Could not open {}: {:?}", file, error)),
+    };
+    decompress(reader).map_err(|error| format!("Could not decompress {}: {:?}", file, error))
+}
+
+// Duplicate paths are rendered in `document`, or as if it were missing when it isn't in memory.
+fn check_duplicates(
+    file: &str,
+    duplicates: &[parser::DuplicateKey],
+    strict: bool,
+    path_style: path::PathStyle,
+    document: &serde_json::Value,
 ) {
-    if left != right {
-        for i in 0..left.len() {
-            if i < right.len() {
-                if left[i] != right[i] {
-                    if left[i].is_array() && right[i].is_array() {
-                        compare_arrays(
-                            left[i].as_array().unwrap(),
-                            right[i].as_array().unwrap(),
-                            patches,
-                            Some(&*format!("{}/{}", prefix.unwrap_or(""), i)),
-                        );
-                    } else if left[i].is_object() && right[i].is_object() {
-                        compare_objects(
-                            left[i].as_object().unwrap(),
-                            right[i].as_object().unwrap(),
-                            patches,
-                            Some(&*format!("{}/{}", prefix.unwrap_or(""), i)),
-                        );
-                    } else {
-                        patches.push(Patch {
-                            op: JsonPatchOp::Replace,
-                            path: format!("{}/{}", prefix.unwrap_or(""), i),
-                            value: Some(right[i].clone()),
-                            old_value: Some(left[i].clone()),
-                        });
-                    }
+    if let Err(error) = try_check_duplicates(file, duplicates, strict, path_style, document) {
+        panic!("{}", error);
+    }
+}
+
+fn try_check_duplicates(
+    file: &str,
+    duplicates: &[parser::DuplicateKey],
+    strict: bool,
+    path_style: path::PathStyle,
+    document: &serde_json::Value,
+) -> Result<(), String> {
+    if duplicates.is_empty() {
+        return Ok(());
+    }
+    let duplicates: Vec<String> = duplicates
+        .iter()
+        .map(|duplicate| {
+            let path = path::render(&duplicate.path, path_style, document);
+            format!("{} at {}", path, duplicate.position)
+        })
+        .collect();
+    if strict {
+        return Err(format!(
+            "Duplicate keys in {}: {}",
+            file,
+            duplicates.join(", ")
+        ));
+    }
+    for duplicate in duplicates {
+        eprintln!("Warning: duplicate key {} in {}", duplicate, file);
+    }
+    Ok(())
+}
+
+fn get_and_parse_contents(
+    file: &str,
+    strict: bool,
+    track_locations: bool,
+    path_style: path::PathStyle,
+) -> parser::Document {
+    read_document(file, strict, track_locations, path_style)
+        .unwrap_or_else(|error| panic!("{}", error))
+}
+
+// Like `get_and_parse_contents`, for callers that carry on when a file can't be read.
+fn read_document(
+    file: &str,
+    strict: bool,
+    track_locations: bool,
+    path_style: path::PathStyle,
+) -> Result<parser::Document, String> {
+    let contents = try_open_contents(file)?;
+    let document = parser::parse(contents, track_locations)
+        .map_err(|error| format!("Could not deserialize {}: {}", file, error))?;
+    try_check_duplicates(
+        file,
+        &document.duplicates,
+        strict,
+        path_style,
+        &document.value,
+    )?;
+    Ok(document)
+}
+
+struct Comparison {
+    left: parser::Document,
+    right: parser::Document,
+    patches: Vec<Patch>,
+}
+
+fn compare_files(file1: &str, file2: &str, args: &Args) -> Comparison {
+    try_compare_files(file1, file2, args).unwrap_or_else(|error| panic!("{}", error))
+}
+
+fn try_compare_files(file1: &str, file2: &str, args: &Args) -> Result<Comparison, String> {
+    let track_locations = args.format.uses_locations();
+    let left = read_document(file1, args.strict, track_locations, args.path_style)?;
+    let right = read_document(file2, args.strict, track_locations, args.path_style)?;
+    let mut patches: Vec<Patch> = Vec::new();
+    compare_with(
+        &left.value,
+        &right.value,
+        &mut patches,
+        &args.compare_options(),
+    );
+    Ok(Comparison {
+        left,
+        right,
+        patches,
+    })
+}
+
+fn sources<'a>(
+    args: &Args,
+    file1: &'a str,
+    file2: &'a str,
+    comparison: &'a Comparison,
+) -> (format::Source<'a>, format::Source<'a>) {
+    (
+        format::Source {
+            name: file1,
+            value: &comparison.left.value,
+            locations: &comparison.left.locations,
+            path_style: args.path_style,
+            string_diff: args.string_diff,
+        },
+        format::Source {
+            name: file2,
+            value: &comparison.right.value,
+            locations: &comparison.right.locations,
+            path_style: args.path_style,
+            string_diff: args.string_diff,
+        },
+    )
+}
+
+fn json_report(
+    args: &Args,
+    file1: &str,
+    file2: &str,
+    comparison: &Comparison,
+) -> serde_json::Value {
+    match args.stat {
+        Some(group) => {
+            let (left, right) = sources(args, file1, file2, comparison);
+            let mut report = stat::stat(&comparison.patches, group, &left, &right).json();
+            report["left"] = serde_json::json!(file1);
+            report["right"] = serde_json::json!(file2);
+            report
+        }
+        None => {
+            let (left, right) = sources(args, file1, file2, comparison);
+            format::json(&comparison.patches, &left, &right)
+        }
+    }
+}
+
+fn html_report(args: &Args, file1: &str, file2: &str, comparison: &Comparison) -> String {
+    let (left, right) = sources(args, file1, file2, comparison);
+    match args.stat {
+        Some(group) => format!(
+            "<h1>Comparing <code>{}</code> and <code>{}</code></h1>\n<pre>{}</pre>\n",
+            format::escape(file1),
+            format::escape(file2),
+            format::escape(&stat::stat(&comparison.patches, group, &left, &right).text())
+        ),
+        None => format::html::html(&comparison.patches, &left, &right),
+    }
+}
+
+fn markdown_report(args: &Args, file1: &str, file2: &str, comparison: &Comparison) -> String {
+    let (left, right) = sources(args, file1, file2, comparison);
+    match args.stat {
+        Some(group) => format!(
+            "### Comparing {} and {}\n\n```\n{}```\n",
+            format::markdown::code(file1),
+            format::markdown::code(file2),
+            stat::stat(&comparison.patches, group, &left, &right).text()
+        ),
+        None => format::markdown::markdown(&comparison.patches, &left, &right),
+    }
+}
+
+fn test_case(
+    args: &Args,
+    name: &str,
+    file1: &str,
+    file2: &str,
+    comparison: &Comparison,
+) -> format::junit::TestCase {
+    let failure = if comparison.patches.is_empty() {
+        None
+    } else {
+        let (left, right) = sources(args, file1, file2, comparison);
+        let message = match comparison.patches.len() {
+            1 => "1 difference".to_string(),
+            n => format!("{} differences", n),
+        };
+        Some((message, format::text(&comparison.patches, &left, &right)))
+    };
+    format::junit::TestCase {
+        name: name.to_string(),
+        failure,
+    }
+}
+
+fn print_differences(
+    out: &mut dyn Write,
+    args: &Args,
+    file1: &str,
+    file2: &str,
+    comparison: &Comparison,
+) -> io::Result<()> {
+    if args.format == Format::Json {
+        writeln!(out, "{}", json_report(args, file1, file2, comparison))?;
+    } else if args.format == Format::Html {
+        let title = format!("Comparing {} and {}", file1, file2);
+        let body = html_report(args, file1, file2, comparison);
+        write!(out, "{}", format::html::page(&title, &body))?;
+    } else if args.format == Format::Markdown {
+        write!(out, "{}", markdown_report(args, file1, file2, comparison))?;
+    } else if args.format == Format::Junit {
+        let name = format!("{} and {}", file1, file2);
+        let case = test_case(args, &name, file1, file2, comparison);
+        write!(out, "{}", format::junit::junit(&[case]))?;
+    } else if args.format == Format::Sarif {
+        let (left, right) = sources(args, file1, file2, comparison);
+        let results = format::sarif::results(&comparison.patches, &left, &right);
+        writeln!(out, "{}", format::sarif::sarif(results))?;
+    } else if comparison.patches.is_empty() {
+        writeln!(out, "No differences were detected.")?;
+    } else if let Some(group) = args.stat {
+        let (left, right) = sources(args, file1, file2, comparison);
+        write!(
+            out,
+            "{}",
+            stat::stat(&comparison.patches, group, &left, &right).text()
+        )?;
+    } else if args.format == Format::Text {
+        let (left, right) = sources(args, file1, file2, comparison);
+        write!(out, "{}", format::text(&comparison.patches, &left, &right))?;
+    } else {
+        let json_patch = if args.old_values {
+            generate_extended_json_patch(&comparison.patches)
+        } else {
+            generate_json_patch(&comparison.patches)
+        }
+        .unwrap_or_else(|error| panic!("Could not write a JSON Patch: {}", error));
+        if json_patch.is_empty() {
+            writeln!(out, "No differences were detected.")?;
+        } else {
+            write!(out, "{}", json_patch_text(&json_patch))?;
+        }
+    }
+    Ok(())
+}
+
+// Patches read from JSON Patch can always be written back.
+fn extended_json_patch(patches: &[Patch]) -> Vec<String> {
+    generate_extended_json_patch(patches)
+        .unwrap_or_else(|error| panic!("Could not write a JSON Patch: {}", error))
+}
+
+// One operation per line.
+fn json_patch_text(json_patch: &[String]) -> String {
+    if json_patch.is_empty() {
+        return "[]\n".to_string();
+    }
+    format!("[\n  {}\n]\n", json_patch.join(",\n  "))
+}
+
+// Only files with differences are printed, followed by a summary. The JSON report includes
+// every compared file, and the HTML and Markdown reports every file with differences. JUnit has a
+// test case for every file, and SARIF a result for every change. Returns whether every file in
+// both directories could be compared.
+fn compare_directories(out: &mut dyn Write, args: &Args) -> io::Result<bool> {
+    let filter = match dir::Filter::new(&args.include, &args.exclude) {
+        Ok(filter) => filter,
+        Err(error) => panic!("Invalid glob: {}", error),
+    };
+    let pairs = match dir::pair(Path::new(&args.file1), Path::new(&args.file2), &filter) {
+        Ok(pairs) => pairs,
+        Err(error) => panic!(
+            "Could not read {} and {}: {:?}",
+            args.file1, args.file2, error
+        ),
+    };
+    if !args.format.is_report() {
+        writeln!(out, "Comparing {} and {}", args.file1, args.file2)?;
+        for file in &pairs.left_only {
+            writeln!(out, "Only in {}: {}", args.file1, file.display())?;
+        }
+        for file in &pairs.right_only {
+            writeln!(out, "Only in {}: {}", args.file2, file.display())?;
+        }
+    }
+    let mut reports: Vec<serde_json::Value> = Vec::new();
+    let mut html = String::new();
+    let mut markdown = String::new();
+    let mut cases: Vec<format::junit::TestCase> = Vec::new();
+    let mut results: Vec<serde_json::Value> = Vec::new();
+    let mut different = 0;
+    let mut errors: Vec<(String, String)> = Vec::new();
+    for file in &pairs.both {
+        let file1 = Path::new(&args.file1).join(file).display().to_string();
+        let file2 = Path::new(&args.file2).join(file).display().to_string();
+        // A file that can't be read or parsed is reported, and the rest are still compared.
+        let comparison = match try_compare_files(&file1, &file2, args) {
+            Ok(comparison) => comparison,
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                if args.format == Format::Junit {
+                    cases.push(format::junit::TestCase {
+                        name: file.display().to_string(),
+                        failure: Some((error.clone(), error.clone())),
+                    });
                 }
-            } else {
-                patches.push(Patch {
-                    op: JsonPatchOp::Remove,
-                    path: format!("{}/{}", prefix.unwrap_or(""), i),
-                    value: None,
-                    old_value: Some(left[i].clone()),
-                });
+                errors.push((file.display().to_string(), error));
+                continue;
             }
+        };
+        if !comparison.patches.is_empty() {
+            different += 1;
         }
-        if right.len() > left.len() {
-            for i in left.len()..right.len() {
-                patches.push(Patch {
-                    op: JsonPatchOp::Add,
-                    path: format!("{}/{}", prefix.unwrap_or(""), i),
-                    value: Some(right[i].clone()),
-                    old_value: None,
-                });
+        if args.format == Format::Json {
+            reports.push(json_report(args, &file1, &file2, &comparison));
+        } else if args.format == Format::Html {
+            if !comparison.patches.is_empty() {
+                html.push_str(&html_report(args, &file1, &file2, &comparison));
+            }
+        } else if args.format == Format::Markdown {
+            if !comparison.patches.is_empty() {
+                markdown.push_str(&markdown_report(args, &file1, &file2, &comparison));
+                markdown.push('\n');
             }
+        } else if args.format == Format::Junit {
+            let name = file.display().to_string();
+            cases.push(test_case(args, &name, &file1, &file2, &comparison));
+        } else if args.format == Format::Sarif {
+            let (left, right) = sources(args, &file1, &file2, &comparison);
+            results.extend(format::sarif::results(&comparison.patches, &left, &right));
+        } else if !comparison.patches.is_empty() {
+            writeln!(out, "Comparing {} and {}", file1, file2)?;
+            print_differences(out, args, &file1, &file2, &comparison)?;
         }
     }
-}
-
-fn compare_objects(
-    left: &serde_json::Map<String, serde_json::Value>,
-    right: &serde_json::Map<String, serde_json::Value>,
-    patches: &mut Vec<Patch>,
-    prefix: Option<&str>,
-) {
-    let left_keys: HashSet<String> = HashSet::from_iter(left.keys().cloned());
-    let right_keys: HashSet<String> = HashSet::from_iter(right.keys().cloned());
-    for key in left_keys.difference(&right_keys) {
-        patches.push(Patch {
-            op: JsonPatchOp::Remove,
-            path: format!("{}/{}", prefix.unwrap_or(""), key),
-            value: None,
-            old_value: Some(left[key].clone()),
-        });
+    let mut summary = format!(
+        "{} files compared, {} with differences, {} only in {}, {} only in {}",
+        pairs.both.len() - errors.len(),
+        different,
+        pairs.left_only.len(),
+        args.file1,
+        pairs.right_only.len(),
+        args.file2
+    );
+    if !errors.is_empty() {
+        summary.push_str(&format!(", {} could not be compared", errors.len()));
     }
-    for key in right_keys.difference(&left_keys) {
-        patches.push(Patch {
-            op: JsonPatchOp::Add,
-            path: format!("{}/{}", prefix.unwrap_or(""), key),
-            value: Some(right[key].clone()),
-            old_value: None,
+    let paths = |files: &Vec<std::path::PathBuf>| -> Vec<String> {
+        files
+            .iter()
+            .map(|file| file.display().to_string())
+            .collect()
+    };
+    if args.format == Format::Json {
+        let report = serde_json::json!({
+            "left": args.file1,
+            "right": args.file2,
+            "files": reports,
+            "left_only": paths(&pairs.left_only),
+            "right_only": paths(&pairs.right_only),
+            "errors": errors
+                .iter()
+                .map(|(file, error)| serde_json::json!({"file": file, "error": error}))
+                .collect::<Vec<serde_json::Value>>(),
+            "summary": {
+                "compared": pairs.both.len() - errors.len(),
+                "different": different,
+                "left_only": pairs.left_only.len(),
+                "right_only": pairs.right_only.len(),
+                "errors": errors.len(),
+            },
         });
-    }
-    for key in left_keys.intersection(&right_keys) {
-        let old = left[key].clone();
-        let new = right[key].clone();
-        if old != new {
-            if old.is_array() && new.is_array() {
-                compare_arrays(
-                    old.as_array().unwrap(),
-                    new.as_array().unwrap(),
-                    patches,
-                    Some(&*format!("{}/{}", prefix.unwrap_or(""), key)),
-                )
-            } else if old.is_object() && new.is_object() {
-                compare_objects(
-                    old.as_object().unwrap(),
-                    new.as_object().unwrap(),
-                    patches,
-                    Some(&*format!("{}/{}", prefix.unwrap_or(""), key)),
-                );
-            } else {
-                patches.push(Patch {
-                    op: JsonPatchOp::Replace,
-                    path: format!("{}/{}", prefix.unwrap_or(""), key),
-                    value: Some(new.clone()),
-                    old_value: Some(old.clone()),
+        writeln!(out, "{}", report)?;
+    } else if args.format == Format::Html {
+        let title = format!("Comparing {} and {}", args.file1, args.file2);
+        let mut body = format!("<h1>{}</h1>\n", format::escape(&title));
+        for (dir, files) in [
+            (&args.file1, paths(&pairs.left_only)),
+            (&args.file2, paths(&pairs.right_only)),
+        ] {
+            for file in files {
+                body.push_str(&format!(
+                    "<p>Only in <code>{}</code>: <code>{}</code></p>\n",
+                    format::escape(dir),
+                    format::escape(&file)
+                ));
+            }
+        }
+        body.push_str(&html);
+        body.push_str(&format!("<p>{}</p>\n", format::escape(&summary)));
+        write!(out, "{}", format::html::page(&title, &body))?;
+    } else if args.format == Format::Markdown {
+        let code = format::markdown::code;
+        writeln!(
+            out,
+            "## Comparing {} and {}\n",
+            code(&args.file1),
+            code(&args.file2)
+        )?;
+        for (dir, files) in [
+            (&args.file1, paths(&pairs.left_only)),
+            (&args.file2, paths(&pairs.right_only)),
+        ] {
+            for file in files {
+                writeln!(out, "* Only in {}: {}", code(dir), code(&file))?;
+            }
+        }
+        if !pairs.left_only.is_empty() || !pairs.right_only.is_empty() {
+            writeln!(out)?;
+        }
+        write!(out, "{}", markdown)?;
+        writeln!(out, "{}", summary)?;
+    } else if args.format == Format::Junit {
+        for (dir, files) in [
+            (&args.file1, paths(&pairs.left_only)),
+            (&args.file2, paths(&pairs.right_only)),
+        ] {
+            for file in files {
+                let message = format!("Only in {}", dir);
+                cases.push(format::junit::TestCase {
+                    name: file,
+                    failure: Some((message.clone(), message)),
                 });
             }
         }
+        write!(out, "{}", format::junit::junit(&cases))?;
+    } else if args.format == Format::Sarif {
+        for (dir, op, files) in [
+            (&args.file1, JsonPatchOp::Remove, &pairs.left_only),
+            (&args.file2, JsonPatchOp::Add, &pairs.right_only),
+        ] {
+            for file in files {
+                let uri = Path::new(dir).join(file).display().to_string();
+                let message = format!("Only in {}: {}", dir, file.display());
+                results.push(format::sarif::file_result(&op, &uri, &message));
+            }
+        }
+        writeln!(out, "{}", format::sarif::sarif(results))?;
+    } else {
+        writeln!(out, "{}", summary)?;
     }
+    Ok(errors.is_empty())
 }
 
-fn generate_json_patch(patches: &Vec<Patch>) -> Vec<String> {
-    let mut ret: Vec<String> = Vec::new();
-    for patch in patches {
-        let json = if patch.op == JsonPatchOp::Remove {
-            serde_json::json!({"op": "remove", "path": patch.path})
+// Prints the JSON Patch as the changes are found. Duplicate keys can only be reported once both
+// files have been read, so with --strict the output is incomplete rather than missing.
+fn stream_files(args: &Args) {
+    let left = open_contents(&args.file1);
+    let right = open_contents(&args.file2);
+    let mut count = 0;
+    let result = stream::compare(left, right, &args.compare_options(), |patch| {
+        if count == 0 {
+            print!("[\n  ");
+        } else {
+            print!(",\n  ");
+        }
+        let operation = if args.old_values {
+            extended_json_patch_operation(&patch)
         } else {
-            serde_json::json!({"op": patch.op.to_string(), "path": patch.path, "value": patch.value})
+            json_patch_operation(&patch)
         };
-        ret.push(json.to_string());
+        match operation {
+            Ok(operation) => print!("{}", operation),
+            Err(error) => panic!("Could not write a JSON Patch: {}", error),
+        }
+        count += 1;
+    });
+    let (left_duplicates, right_duplicates) = match result {
+        Ok(duplicates) => duplicates,
+        Err(stream::Error::Left(error)) => {
+            panic!("Could not deserialize {}: {}", args.file1, error)
+        }
+        Err(stream::Error::Right(error)) => {
+            panic!("Could not deserialize {}: {}", args.file2, error)
+        }
+    };
+    if count == 0 {
+        println!("No differences were detected.");
+    } else {
+        println!("\n]");
+    }
+    for (file, duplicates) in [
+        (&args.file1, left_duplicates),
+        (&args.file2, right_duplicates),
+    ] {
+        let document = serde_json::Value::Null;
+        check_duplicates(file, &duplicates, args.strict, args.path_style, &document);
     }
-    ret
 }
 
-fn get_and_parse_contents(file: String) -> serde_json::Value {
-    let result = fs::read_to_string(&file);
-    let contents = match result {
-        Ok(contents) => contents,
-        Err(error) => panic!("Could not open {}: {:?}", file, error),
+// Exits with status 1 if there are conflicts, where our side is kept.
+fn merge_files(args: &MergeArgs) {
+    let [base, ours, theirs] = [&args.base, &args.ours, &args.theirs].map(|file| {
+        get_and_parse_contents(file, args.strict, false, path::PathStyle::Pointer).value
+    });
+    let options = CompareOptions {
+        exact_numbers: args.exact_numbers,
+        ..Default::default()
+    };
+    let merge = merge::merge(&base, &ours, &theirs, &options);
+    let output = match args.format {
+        merge::Format::Document => {
+            for conflict in &merge.conflicts {
+                eprintln!("Conflict at {}", conflict.path);
+            }
+            match serde_json::to_string_pretty(&merge.value) {
+                Ok(output) => output,
+                Err(error) => panic!("Could not serialize the merged document: {}", error),
+            }
+        }
+        merge::Format::Json => merge.json().to_string(),
     };
-    match serde_json::from_str(contents.as_str()) {
-        Ok(value) => value,
-        Err(error) => panic!("Could not deserialize {}: {:?}", file, error),
+    write_output(&args.output, &(output + "\n"));
+    if !merge.conflicts.is_empty() {
+        process::exit(1);
     }
 }
 
-fn main() {
-    let args = Args::parse();
-    println!("Comparing {} and {}", args.file1, args.file2);
-
-    let left = get_and_parse_contents(args.file1);
-    let right = get_and_parse_contents(args.file2);
+// Returns whether the files merged without conflicts, which are marked in the file otherwise. Git
+// treats a driver that fails as a conflict too, and leaves our side, so files that can't be parsed
+// are left to be resolved by hand.
+fn git_merge_driver(args: &GitMergeDriverArgs) -> bool {
+    let ours = get_and_parse_contents(&args.ours, false, false, path::PathStyle::Pointer).value;
+    let theirs = get_and_parse_contents(&args.theirs, false, false, path::PathStyle::Pointer).value;
+    // Files added on both sides are merged with an empty base of their type, so that members or
+    // elements that only one side added don't conflict.
+    let base = match fs::metadata(&args.base) {
+        Ok(metadata) if metadata.len() == 0 => match (&ours, &theirs) {
+            (serde_json::Value::Object(_), serde_json::Value::Object(_)) => serde_json::json!({}),
+            (serde_json::Value::Array(_), serde_json::Value::Array(_)) => serde_json::json!([]),
+            _ => serde_json::Value::Null,
+        },
+        _ => get_and_parse_contents(&args.base, false, false, path::PathStyle::Pointer).value,
+    };
+    let options = CompareOptions {
+        exact_numbers: args.exact_numbers,
+        ..Default::default()
+    };
+    let merge = merge::merge(&base, &ours, &theirs, &options);
+    let name = args.path.as_deref().unwrap_or(&args.ours);
+    for conflict in &merge.conflicts {
+        eprintln!("Conflict in {} at {}", name, conflict.path);
+    }
+    let output = merge.conflict_markers("ours", "theirs");
+    if let Err(error) = fs::write(&args.ours, output) {
+        panic!("Could not write {}: {:?}", args.ours, error);
+    }
+    merge.conflicts.is_empty()
+}
 
+// Git passes /dev/null for the missing side of an added or deleted file, which is reported as
+// adding or removing the whole document.
+fn git_diff(out: &mut dyn Write, args: &GitDiffArgs) -> io::Result<()> {
+    let (old_path, old_file, new_file, new_path) = match args.git.as_slice() {
+        [path] => {
+            writeln!(out, "* Unmerged path {}", path)?;
+            return Ok(());
+        }
+        [path, old_file, _, _, new_file, _, _] => (path, old_file, new_file, path),
+        [path, old_file, _, _, new_file, _, _, new_path, _] => (path, old_file, new_file, new_path),
+        git => panic!("Expected 1, 7 or 9 arguments from git, not {}", git.len()),
+    };
+    let diff_args = Args {
+        file1: format!("a/{}", old_path),
+        file2: format!("b/{}", new_path),
+        exact_numbers: args.exact_numbers,
+        strict: false,
+        report_reordering: args.report_reordering,
+        format: args.format,
+        path_style: args.path_style,
+        stat: None,
+        include: Vec::new(),
+        exclude: Vec::new(),
+        stream: false,
+        jobs: 1,
+        old_values: false,
+        string_diff: args.string_diff,
+        parse_embedded_json: args.parse_embedded_json.clone(),
+        normalize: args.normalize.clone(),
+        null_equals_missing: args.null_equals_missing,
+        empty_equals_missing: args.empty_equals_missing,
+    };
+    let track_locations = args.format.uses_locations();
+    let parse = |file: &str| match file {
+        "/dev/null" => Ok(None),
+        file => read_document(file, false, track_locations, args.path_style).map(Some),
+    };
+    // A file that isn't JSON is reported the way git reports binary files, so that the rest of
+    // the diff is still shown.
+    let (left, right) = match (parse(old_file), parse(new_file)) {
+        (Ok(left), Ok(right)) => (left, right),
+        (Err(error), _) | (_, Err(error)) => {
+            eprintln!("Warning: {}", error);
+            writeln!(
+                out,
+                "Files {} and {} differ",
+                diff_args.file1, diff_args.file2
+            )?;
+            return Ok(());
+        }
+    };
     let mut patches: Vec<Patch> = Vec::new();
+    match (&left, &right) {
+        (Some(left), Some(right)) => compare_with(
+            &left.value,
+            &right.value,
+            &mut patches,
+            &diff_args.compare_options(),
+        ),
+        (None, Some(right)) => patches.push(Patch {
+            op: JsonPatchOp::Add,
+            path: "/".to_string(),
+            value: Some(right.value.clone()),
+            old_value: None,
+        }),
+        (Some(left), None) => patches.push(Patch {
+            op: JsonPatchOp::Remove,
+            path: "/".to_string(),
+            value: None,
+            old_value: Some(left.value.clone()),
+        }),
+        (None, None) => (),
+    }
+    let empty = || parser::Document {
+        value: serde_json::Value::Null,
+        duplicates: Vec::new(),
+        locations: parser::Locations::new(),
+    };
+    let comparison = Comparison {
+        left: left.unwrap_or_else(empty),
+        right: right.unwrap_or_else(empty),
+        patches,
+    };
+    if !args.format.is_report() {
+        writeln!(out, "Comparing {} and {}", diff_args.file1, diff_args.file2)?;
+    }
+    print_differences(
+        out,
+        &diff_args,
+        &diff_args.file1,
+        &diff_args.file2,
+        &comparison,
+    )
+}
 
-    compare(&left, &right, &mut patches);
-    if patches.is_empty() {
-        println!("No differences were detected.");
-    } else {
-        println!("[");
-        for (i, patch) in generate_json_patch(&patches).iter().enumerate() {
-            let suffix = if i + 1 < patches.len() { "," } else { "" };
-            println!("  {}{}", patch, suffix);
+fn write_stdout<T>(write: impl FnOnce(&mut dyn Write) -> io::Result<T>) -> T {
+    match write(&mut io::stdout().lock()) {
+        Ok(ret) => ret,
+        Err(error) => panic!("Could not write to standard output: {:?}", error),
+    }
+}
+
+// To standard output without a file.
+fn write_output(file: &Option<String>, output: &str) {
+    match file {
+        Some(file) => {
+            if let Err(error) = fs::write(file, output) {
+                panic!("Could not write {}: {:?}", file, error);
+            }
+        }
+        None => print!("{}", output),
+    }
+}
+
+fn read_json_patch(file: &str) -> Vec<Patch> {
+    let json_patch: serde_json::Value = match serde_json::from_reader(open_contents(file)) {
+        Ok(json_patch) => json_patch,
+        Err(error) => panic!("Could not deserialize {}: {}", file, error),
+    };
+    match parse_json_patch(&json_patch) {
+        Ok(patches) => patches,
+        Err(error) => panic!("Could not read {}: {}", file, error),
+    }
+}
+
+fn invert_patch(args: &InvertArgs) {
+    let patches = read_json_patch(&args.patch);
+    // The inverse has old values too, so that it can be inverted back.
+    let inverse = match invert::invert(&patches) {
+        Ok(inverse) => inverse,
+        Err(error) => panic!("Could not invert {}: {}", args.patch, error),
+    };
+    write_output(
+        &args.output,
+        &json_patch_text(&extended_json_patch(&inverse)),
+    );
+}
+
+fn compose_patches(args: &ComposeArgs) {
+    let patches: Vec<Patch> = args
+        .patches
+        .iter()
+        .flat_map(|file| read_json_patch(file))
+        .collect();
+    let composed = compose::compose(patches);
+    write_output(
+        &args.output,
+        &json_patch_text(&extended_json_patch(&composed)),
+    );
+}
+
+// Exits with status 1 if some operations can't be rebased, which are left out of the patch.
+fn rebase_patch(args: &RebaseArgs) {
+    let patches = read_json_patch(&args.patch);
+    let onto = match (&args.onto, &args.old, &args.new) {
+        (Some(onto), _, _) => read_json_patch(onto),
+        (None, Some(old), Some(new)) => {
+            let old = get_and_parse_contents(old, false, false, path::PathStyle::Pointer);
+            let new = get_and_parse_contents(new, false, false, path::PathStyle::Pointer);
+            rebase::changes(&old.value, &new.value)
+        }
+        _ => unreachable!("clap requires OLD and NEW without --onto"),
+    };
+    let rebase = rebase::rebase(&patches, &onto);
+    for conflict in &rebase.conflicts {
+        eprintln!(
+            "Could not rebase {} at {}: {}",
+            conflict.patch.op, conflict.patch.path, conflict.reason
+        );
+    }
+    write_output(
+        &args.output,
+        &json_patch_text(&extended_json_patch(&rebase.patches)),
+    );
+    if !rebase.conflicts.is_empty() {
+        process::exit(1);
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let args = match (cli.command, cli.args) {
+        (Some(Command::Merge(args)), _) => return merge_files(&args),
+        (Some(Command::GitMergeDriver(args)), _) => {
+            // Git expects status 1 when there are conflicts.
+            if !git_merge_driver(&args) {
+                process::exit(1);
+            }
+            return;
+        }
+        (Some(Command::GitDiff(args)), _) => return write_stdout(|out| git_diff(out, &args)),
+        (Some(Command::Invert(args)), _) => return invert_patch(&args),
+        (Some(Command::Compose(args)), _) => return compose_patches(&args),
+        (Some(Command::Rebase(args)), _) => return rebase_patch(&args),
+        (None, Some(args)) => args,
+        (None, None) => unreachable!("clap requires the files"),
+    };
+    if args.jobs != 1 {
+        let result = rayon::ThreadPoolBuilder::new()
+            .num_threads(args.jobs)
+            .build_global();
+        if let Err(error) = result {
+            panic!("Could not start {} threads: {}", args.jobs, error);
+        }
+    }
+    if Path::new(&args.file1).is_dir() && Path::new(&args.file2).is_dir() {
+        if args.stream {
+            panic!("--stream can only compare two files");
+        }
+        // Files that couldn't be compared fail the run, as a single file does.
+        if !write_stdout(|out| compare_directories(out, &args)) {
+            process::exit(1);
         }
-        println!("]");
+        return;
     }
+    if args.stream && args.format != Format::Patch {
+        panic!("--stream only supports --format patch");
+    }
+    if !args.format.is_report() {
+        println!("Comparing {} and {}", args.file1, args.file2);
+    }
+    if args.stream {
+        stream_files(&args);
+        return;
+    }
+    let comparison = compare_files(&args.file1, &args.file2, &args);
+    write_stdout(|out| print_differences(out, &args, &args.file1, &args.file2, &comparison));
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{compare, JsonPatchOp, Patch};
+    use crate::{
+        compare_directories, decompress, git_diff, git_merge_driver, Args, Cli, Command,
+        GitMergeDriverArgs,
+    };
+    use clap::Parser;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::path::Path;
 
-    #[test]
-    fn test_compare_array_nop() {
-        let doc = serde_json::json!(["a", "b"]);
-        let mut patches: Vec<Patch> = Vec::new();
-        compare(&doc, &doc.clone(), &mut patches);
-        assert!(patches.is_empty());
+    fn args(arguments: &[&str]) -> Args {
+        let cli = Cli::parse_from([&["jsondiff"], arguments].concat());
+        cli.args.unwrap()
+    }
+
+    fn write_files(dir: &Path, files: &[(&str, &str)]) {
+        for (file, contents) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+    }
+
+    fn decompressed(bytes: Vec<u8>) -> Vec<u8> {
+        let mut contents = Vec::new();
+        decompress(bytes.as_slice())
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        contents
     }
 
     #[test]
-    fn test_compare_array_added_values() {
-        let left = serde_json::json!(["a", "b"]);
-        let right = serde_json::json!(["a", "b", "c", "d"]);
-        let mut patches: Vec<Patch> = Vec::new();
-        compare(&left, &right.clone(), &mut patches);
-        let expected = vec![
-            Patch {
-                op: JsonPatchOp::Add,
-                path: "/2".to_string(),
-                value: Some(serde_json::json!("c")),
-                old_value: None,
-            },
-            Patch {
-                op: JsonPatchOp::Add,
-                path: "/3".to_string(),
-                value: Some(serde_json::json!("d")),
-                old_value: None,
-            },
-        ];
-        assert_eq!(expected, patches);
+    fn test_decompress_plain() {
+        let contents = br#"{"string": "This is a string."}"#.to_vec();
+        assert_eq!(contents, decompressed(contents.clone()));
     }
 
     #[test]
-    fn test_compare_array_changed_value() {
-        let left = serde_json::json!(["a", "b", "c"]);
-        let right = serde_json::json!(["a", 2, "c"]);
-        let mut patches: Vec<Patch> = Vec::new();
-        compare(&left, &right.clone(), &mut patches);
-        let expected = vec![Patch {
-            op: JsonPatchOp::Replace,
-            path: "/1".to_string(),
-            value: Some(serde_json::json!(2)),
-            old_value: Some(serde_json::json!("b")),
-        }];
-        assert_eq!(expected, patches);
+    fn test_decompress_gzip() {
+        let contents = br#"{"string": "This is a string."}"#.to_vec();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&contents).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(contents, decompressed(compressed));
     }
 
     #[test]
-    fn test_compare_array_changed_nested_value() {
-        let left = serde_json::json!(["a", ["b", "c"], "d"]);
-        let right = serde_json::json!(["a", ["b", 3], "d"]);
-        let mut patches: Vec<Patch> = Vec::new();
-        compare(&left, &right.clone(), &mut patches);
-        let expected = vec![Patch {
-            op: JsonPatchOp::Replace,
-            path: "/1/1".to_string(),
-            value: Some(serde_json::json!(3)),
-            old_value: Some(serde_json::json!("c")),
-        }];
-        assert_eq!(expected, patches);
+    fn test_compare_directories() {
+        let left = tempfile::tempdir().unwrap();
+        let right = tempfile::tempdir().unwrap();
+        write_files(
+            left.path(),
+            &[
+                ("same.json", r#"{"a": 1}"#),
+                ("users/1.json", r#"{"name": "Al"}"#),
+                ("tmp/x.json", "1"),
+                ("notes.txt", "a"),
+                ("old.json", "1"),
+            ],
+        );
+        write_files(
+            right.path(),
+            &[
+                ("same.json", r#"{"a": 1}"#),
+                ("users/1.json", r#"{"name": "Sam"}"#),
+                ("tmp/x.json", "2"),
+                ("notes.txt", "b"),
+                ("users/new.json", "1"),
+            ],
+        );
+        let (left, right) = (
+            left.path().display().to_string(),
+            right.path().display().to_string(),
+        );
+        let args = args(&[
+            &left,
+            &right,
+            "--include",
+            "**/*.json",
+            "--exclude",
+            "tmp/**",
+        ]);
+        let mut out: Vec<u8> = Vec::new();
+        assert!(compare_directories(&mut out, &args).unwrap());
+        let users = |dir: &str| Path::new(dir).join("users/1.json").display().to_string();
+        let expected = format!(
+            "Comparing {left} and {right}\n\
+             Only in {left}: old.json\n\
+             Only in {right}: {new}\n\
+             Comparing {left_users} and {right_users}\n\
+             [\n  {{\"op\":\"replace\",\"path\":\"/name\",\"value\":\"Sam\"}}\n]\n\
+             2 files compared, 1 with differences, 1 only in {left}, 1 only in {right}\n",
+            new = Path::new("users").join("new.json").display(),
+            left_users = users(&left),
+            right_users = users(&right),
+        );
+        assert_eq!(expected, String::from_utf8(out).unwrap());
     }
 
     #[test]
-    fn test_compare_array_removed_value() {
-        let left = serde_json::json!(["a", "b", "c", "d"]);
-        let right = serde_json::json!(["a", "b"]);
-        let mut patches: Vec<Patch> = Vec::new();
-        compare(&left, &right.clone(), &mut patches);
-        let expected = vec![
-            Patch {
-                op: JsonPatchOp::Remove,
-                path: "/2".to_string(),
-                value: None,
-                old_value: Some(serde_json::json!("c")),
-            },
-            Patch {
-                op: JsonPatchOp::Remove,
-                path: "/3".to_string(),
-                value: None,
-                old_value: Some(serde_json::json!("d")),
-            },
-        ];
-        assert_eq!(expected, patches);
+    fn test_compare_directories_errors() {
+        let left = tempfile::tempdir().unwrap();
+        let right = tempfile::tempdir().unwrap();
+        write_files(left.path(), &[("a.json", "1"), ("b.json", "{")]);
+        write_files(right.path(), &[("a.json", "2"), ("b.json", "{}")]);
+        let args = args(&[
+            &left.path().display().to_string(),
+            &right.path().display().to_string(),
+            "--format",
+            "json",
+        ]);
+        let mut out: Vec<u8> = Vec::new();
+        // The other files are still compared.
+        assert!(!compare_directories(&mut out, &args).unwrap());
+        let report: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(
+            serde_json::json!({
+                "compared": 1,
+                "different": 1,
+                "left_only": 0,
+                "right_only": 0,
+                "errors": 1
+            }),
+            report["summary"]
+        );
+        assert_eq!("b.json", report["errors"][0]["file"]);
+    }
+
+    // Merges the files as git would, returning whether they merged cleanly and the merged file.
+    fn merge_driver(base: &str, ours: &str, theirs: &str) -> (bool, String) {
+        let dir = tempfile::tempdir().unwrap();
+        write_files(
+            dir.path(),
+            &[("base", base), ("ours", ours), ("theirs", theirs)],
+        );
+        let file = |name: &str| dir.path().join(name).display().to_string();
+        let args = GitMergeDriverArgs {
+            base: file("base"),
+            ours: file("ours"),
+            theirs: file("theirs"),
+            path: Some("config.json".to_string()),
+            exact_numbers: false,
+        };
+        let merged = git_merge_driver(&args);
+        (merged, fs::read_to_string(file("ours")).unwrap())
     }
 
     #[test]
-    fn test_compare_object_nop() {
-        let doc = serde_json::json!({
-            "string": "This is a string.",
-            "integer": 42,
-            "float": 3.14159,
-            "object": {
-                "substring": "This is another string."
-            },
-            "array": ["one", "two"],
-            "boolean": true,
-            "null": null
-        });
-        let mut patches: Vec<Patch> = Vec::new();
-        compare(&doc, &doc.clone(), &mut patches);
-        assert!(patches.is_empty());
+    fn test_git_merge_driver() {
+        let (merged, output) = merge_driver(
+            r#"{"a": 1, "b": 1}"#,
+            r#"{"a": 2, "b": 1}"#,
+            r#"{"a": 1, "b": 2}"#,
+        );
+        assert!(merged);
+        assert_eq!("{\n  \"a\": 2,\n  \"b\": 2\n}\n", output);
+        // Conflicts are marked in our file.
+        let (merged, output) = merge_driver(r#"{"a": 1}"#, r#"{"a": 2}"#, r#"{"a": 3}"#);
+        assert!(!merged);
+        assert_eq!(
+            "{\n<<<<<<< ours\n  \"a\": 2\n=======\n  \"a\": 3\n>>>>>>> theirs\n}\n",
+            output
+        );
     }
 
     #[test]
-    fn test_compare_object_added() {
-        let left = serde_json::json!({});
-        let right = serde_json::json!({
-            "string": "This is a string.",
-            "integer": 42,
-            "float": 3.14159,
-            "object": {
-                "substring": "This is another string."
-            },
-            "array": ["one", "two"],
-            "boolean": true,
-            "null": null
-        });
-        let mut patches: Vec<Patch> = Vec::new();
-        compare(&left, &right, &mut patches);
-        let expected = vec![
-            Patch {
-                op: JsonPatchOp::Add,
-                path: "/string".to_string(),
-                value: Some(serde_json::json!("This is a string.")),
-                old_value: None,
-            },
-            Patch {
-                op: JsonPatchOp::Add,
-                path: "/integer".to_string(),
-                value: Some(serde_json::json!(42)),
-                old_value: None,
-            },
-            Patch {
-                op: JsonPatchOp::Add,
-                path: "/float".to_string(),
-                value: Some(serde_json::json!(3.14159)),
-                old_value: None,
-            },
-            Patch {
-                op: JsonPatchOp::Add,
-                path: "/object".to_string(),
-                value: Some(serde_json::json!({"substring": "This is another string."})),
-                old_value: None,
-            },
-            Patch {
-                op: JsonPatchOp::Add,
-                path: "/array".to_string(),
-                value: Some(serde_json::json!(["one", "two"])),
-                old_value: None,
-            },
-            Patch {
-                op: JsonPatchOp::Add,
-                path: "/boolean".to_string(),
-                value: Some(serde_json::json!(true)),
-                old_value: None,
-            },
-            Patch {
-                op: JsonPatchOp::Add,
-                path: "/null".to_string(),
-                value: Some(serde_json::json!(null)),
-                old_value: None,
-            },
-        ];
-        assert_eq!(expected.len(), patches.len());
-        assert!(expected.iter().all(|item| patches.contains(item)));
+    fn test_git_merge_driver_added() {
+        // Files added on both branches have an empty base.
+        let (merged, output) = merge_driver("", r#"{"a": 1}"#, r#"{"b": 2}"#);
+        assert!(merged);
+        assert_eq!("{\n  \"a\": 1,\n  \"b\": 2\n}\n", output);
+        let (merged, output) = merge_driver("", "[1]", "[1, 2]");
+        assert!(merged);
+        assert_eq!("[\n  1,\n  2\n]\n", output);
+        // Unless the files are different types, which conflict.
+        let (merged, output) = merge_driver("", r#"{"a": 1}"#, "[1]");
+        assert!(!merged);
+        assert!(output.starts_with("<<<<<<< ours\n{\n  \"a\": 1\n}\n=======\n[\n"));
+    }
+
+    // The output of an external diff called with the arguments git passes, with OLD and NEW
+    // standing for files with those contents.
+    fn external_diff(git: &[&str], old: &str, new: &str) -> String {
+        let dir = tempfile::tempdir().unwrap();
+        write_files(dir.path(), &[("old", old), ("new", new)]);
+        let file = |name: &str| dir.path().join(name).display().to_string();
+        let git: Vec<String> = git
+            .iter()
+            .map(|arg| match *arg {
+                "OLD" => file("old"),
+                "NEW" => file("new"),
+                arg => arg.to_string(),
+            })
+            .collect();
+        let cli =
+            Cli::parse_from([vec!["jsondiff".to_string(), "git-diff".to_string()], git].concat());
+        let Some(Command::GitDiff(args)) = cli.command else {
+            panic!("not git-diff");
+        };
+        let mut out: Vec<u8> = Vec::new();
+        git_diff(&mut out, &args).unwrap();
+        String::from_utf8(out).unwrap()
     }
 
     #[test]
-    fn test_compare_object_removed() {
-        let left = serde_json::json!({
-            "string": "This is a string.",
-            "integer": 42,
-            "float": 3.14159,
-            "object": {
-                "substring": "This is another string."
-            },
-            "array": ["one", "two"],
-            "boolean": true,
-            "null": null
-        });
-        let right = serde_json::json!({});
-        let mut patches: Vec<Patch> = Vec::new();
-        compare(&left, &right, &mut patches);
-        let expected = vec![
-            Patch {
-                op: JsonPatchOp::Remove,
-                path: "/string".to_string(),
-                value: None,
-                old_value: Some(serde_json::json!("This is a string.")),
-            },
-            Patch {
-                op: JsonPatchOp::Remove,
-                path: "/integer".to_string(),
-                value: None,
-                old_value: Some(serde_json::json!(42)),
-            },
-            Patch {
-                op: JsonPatchOp::Remove,
-                path: "/float".to_string(),
-                value: None,
-                old_value: Some(serde_json::json!(3.14159)),
-            },
-            Patch {
-                op: JsonPatchOp::Remove,
-                path: "/object".to_string(),
-                value: None,
-                old_value: Some(serde_json::json!({"substring": "This is another string."})),
-            },
-            Patch {
-                op: JsonPatchOp::Remove,
-                path: "/array".to_string(),
-                value: None,
-                old_value: Some(serde_json::json!(["one", "two"])),
-            },
-            Patch {
-                op: JsonPatchOp::Remove,
-                path: "/boolean".to_string(),
-                value: None,
-                old_value: Some(serde_json::json!(true)),
-            },
-            Patch {
-                op: JsonPatchOp::Remove,
-                path: "/null".to_string(),
-                value: None,
-                old_value: Some(serde_json::json!(null)),
-            },
+    fn test_git_diff() {
+        let modified = ["conf.json", "OLD", "1", "100644", "NEW", "2", "100644"];
+        assert_eq!(
+            "Comparing a/conf.json and b/conf.json\n\
+             replace /a (a/conf.json:1:7, b/conf.json:1:7)\n  - 1\n  + 2\n",
+            external_diff(&modified, r#"{"a": 1}"#, r#"{"a": 2}"#)
+        );
+        let renamed = [
+            "old.json",
+            "OLD",
+            "1",
+            "100644",
+            "NEW",
+            "2",
+            "100644",
+            "new.json",
+            "similarity index 90%",
         ];
-        assert_eq!(expected.len(), patches.len());
-        assert!(expected.iter().all(|item| patches.contains(item)));
+        assert_eq!(
+            "Comparing a/old.json and b/new.json\n\
+             replace /a (a/old.json:1:7, b/new.json:1:7)\n  - 1\n  + 2\n",
+            external_diff(&renamed, r#"{"a": 1}"#, r#"{"a": 2}"#)
+        );
+        assert_eq!(
+            "* Unmerged path conf.json\n",
+            external_diff(&["conf.json"], "", "")
+        );
     }
 
     #[test]
-    fn test_compare_object_replaced() {
-        let left = serde_json::json!({
-            "string": "There are strange things done in the midnight sun",
-            "integer": 42,
-            "float": 3.14159,
-            "object": {
-                "substring": "By the men who moil for gold;"
-            },
-            "array": ["one", "two"],
-            "boolean": true,
-            "null": null
-        });
-        let right = serde_json::json!({
-            "string": "The Arctic trails have their secret tales",
-            "integer": 60606,
-            "float": 2.71828,
-            "object": {
-                "substring": "That would make your blood run cold;"
-            },
-            "array": ["a", "b"],
-            "boolean": false,
-            "null": "NOT NULL"
-        });
-        let mut patches: Vec<Patch> = Vec::new();
-        compare(&left, &right, &mut patches);
-        let expected = vec![
-            Patch {
-                op: JsonPatchOp::Replace,
-                path: "/string".to_string(),
-                value: Some(serde_json::json!(
-                    "The Arctic trails have their secret tales"
-                )),
-                old_value: Some(serde_json::json!(
-                    "There are strange things done in the midnight sun"
-                )),
-            },
-            Patch {
-                op: JsonPatchOp::Replace,
-                path: "/integer".to_string(),
-                value: Some(serde_json::json!(60606)),
-                old_value: Some(serde_json::json!(42)),
-            },
-            Patch {
-                op: JsonPatchOp::Replace,
-                path: "/float".to_string(),
-                value: Some(serde_json::json!(2.71828)),
-                old_value: Some(serde_json::json!(3.14159)),
-            },
-            Patch {
-                op: JsonPatchOp::Replace,
-                path: "/object/substring".to_string(),
-                value: Some(serde_json::json!("That would make your blood run cold;")),
-                old_value: Some(serde_json::json!("By the men who moil for gold;")),
-            },
-            Patch {
-                op: JsonPatchOp::Replace,
-                path: "/array/0".to_string(),
-                value: Some(serde_json::json!("a")),
-                old_value: Some(serde_json::json!("one")),
-            },
-            Patch {
-                op: JsonPatchOp::Replace,
-                path: "/array/1".to_string(),
-                value: Some(serde_json::json!("b")),
-                old_value: Some(serde_json::json!("two")),
-            },
-            Patch {
-                op: JsonPatchOp::Replace,
-                path: "/boolean".to_string(),
-                value: Some(serde_json::json!(false)),
-                old_value: Some(serde_json::json!(true)),
-            },
-            Patch {
-                op: JsonPatchOp::Replace,
-                path: "/null".to_string(),
-                value: Some(serde_json::json!("NOT NULL")),
-                old_value: Some(serde_json::json!(null)),
-            },
-        ];
-        assert_eq!(expected.len(), patches.len());
-        assert!(expected.iter().all(|item| patches.contains(item)));
+    fn test_git_diff_added_removed() {
+        let added = ["conf.json", "/dev/null", ".", ".", "NEW", "2", "100644"];
+        assert_eq!(
+            "Comparing a/conf.json and b/conf.json\nadd / (b/conf.json:1:1)\n  + {\"a\":2}\n",
+            external_diff(&added, "", r#"{"a": 2}"#)
+        );
+        let removed = ["conf.json", "OLD", "1", "100644", "/dev/null", ".", "."];
+        assert_eq!(
+            "Comparing a/conf.json and b/conf.json\nremove / (a/conf.json:1:1)\n  - {\"a\":1}\n",
+            external_diff(&removed, r#"{"a": 1}"#, "")
+        );
     }
 
     #[test]
-    fn test_compare_string_nop() {
-        let doc = serde_json::json!("There are strange things done in the midnight sun");
-        let mut patches: Vec<Patch> = Vec::new();
-        compare(&doc, &doc.clone(), &mut patches);
-        assert!(patches.is_empty());
+    fn test_git_diff_not_json() {
+        let modified = ["notes.json", "OLD", "1", "100644", "NEW", "2", "100644"];
+        assert_eq!(
+            "Files a/notes.json and b/notes.json differ\n",
+            external_diff(&modified, r#"{"a": 1}"#, "not JSON")
+        );
     }
 
     #[test]
-    fn test_compare_string_changed_value() {
-        let left = serde_json::json!("There are strange things done in the midnight sun");
-        let right = serde_json::json!("By the men who moil for gold;");
-        let mut patches: Vec<Patch> = Vec::new();
-        compare(&left, &right.clone(), &mut patches);
-        let expected = vec![Patch {
-            op: JsonPatchOp::Replace,
-            path: "/".to_string(),
-            value: Some(serde_json::json!("By the men who moil for gold;")),
-            old_value: Some(serde_json::json!(
-                "There are strange things done in the midnight sun"
-            )),
-        }];
-        assert_eq!(expected, patches);
+    #[should_panic(expected = "Expected 1, 7 or 9 arguments from git, not 3")]
+    fn test_git_diff_arguments() {
+        external_diff(&["conf.json", "OLD", "NEW"], "1", "2");
     }
 
     #[test]
-    fn test_compare_string_changed_type() {
-        let left = serde_json::json!("There are strange things done in the midnight sun");
-        let right = serde_json::json!(42);
-        let mut patches: Vec<Patch> = Vec::new();
-        compare(&left, &right.clone(), &mut patches);
-        let expected = vec![Patch {
-            op: JsonPatchOp::Replace,
-            path: "/".to_string(),
-            value: Some(serde_json::json!(42)),
-            old_value: Some(serde_json::json!(
-                "There are strange things done in the midnight sun"
-            )),
-        }];
-        assert_eq!(expected, patches);
+    fn test_decompress_zstd() {
+        let contents = br#"{"string": "This is a string."}"#.to_vec();
+        let compressed = zstd::stream::encode_all(contents.as_slice(), 0).unwrap();
+        assert_eq!(contents, decompressed(compressed));
     }
 }
@@ -0,0 +1,605 @@
+// Three-way merge: both sides are compared with their common base, and their changes are
+// combined. Our changes are kept, and each of their changes is applied on top unless it
+// overlaps one of ours, i.e. touches the same path or one above or below it. Overlapping changes
+// are conflicts, unless both sides made the same change. So is an array both sides changed where
+// either one may have shifted its elements.
+
+use crate::apply::apply_one;
+use crate::{compare_with, equal, CompareOptions, JsonPatchOp, Patch};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum Format {
+    /// The merged document, with conflicts listed on standard error
+    Document,
+    /// A JSON report of the merged document and the conflicts
+    Json,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Conflict {
+    // The highest path either side changed, so that the values show both changes in full.
+    pub path: String,
+    // None where the value doesn't exist on that side.
+    pub base: Option<serde_json::Value>,
+    pub ours: Option<serde_json::Value>,
+    pub theirs: Option<serde_json::Value>,
+}
+
+impl Conflict {
+    pub fn json(&self) -> serde_json::Value {
+        let mut ret = serde_json::json!({"path": self.path});
+        for (side, value) in [
+            ("base", &self.base),
+            ("ours", &self.ours),
+            ("theirs", &self.theirs),
+        ] {
+            if let Some(value) = value {
+                ret[side] = value.clone();
+            }
+        }
+        ret
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Merge {
+    // Where there are conflicts, our side is kept.
+    pub value: serde_json::Value,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl Merge {
+    pub fn json(&self) -> serde_json::Value {
+        let conflicts: Vec<serde_json::Value> = self.conflicts.iter().map(Conflict::json).collect();
+        serde_json::json!({"merged": self.value, "conflicts": conflicts})
+    }
+
+    // The merged document, formatted like `serde_json::to_string_pretty`, with each conflict
+    // marked the way git marks them in text: our side between `<<<<<<<` and `=======`, theirs
+    // between `=======` and `>>>>>>>`. Each side is the whole member (or nothing, where it doesn't
+    // exist), so that keeping either side of every conflict leaves valid JSON.
+    pub fn conflict_markers(&self, ours: &str, theirs: &str) -> String {
+        let mut markers = Markers {
+            conflicts: self
+                .conflicts
+                .iter()
+                .map(|conflict| (conflict.path.as_str(), conflict))
+                .collect(),
+            above: self
+                .conflicts
+                .iter()
+                .filter(|conflict| conflict.path != "/")
+                .flat_map(|conflict| ancestors(&conflict.path))
+                .collect(),
+            ours,
+            theirs,
+            out: String::new(),
+        };
+        match markers.conflicts.get("/") {
+            Some(conflict) => {
+                markers.conflict(conflict, "", Some(&self.value), "", "");
+            }
+            None => {
+                markers.value(&self.value, "/", "");
+                markers.out.push('\n');
+            }
+        }
+        markers.out
+    }
+}
+
+struct Member<'a> {
+    path: String,
+    // The key and colon in an object, or nothing in an array.
+    key: String,
+    // In the merged document, which is our side where there are conflicts.
+    value: Option<&'a serde_json::Value>,
+    conflict: Option<&'a Conflict>,
+}
+
+impl Member<'_> {
+    fn one_sided(&self) -> bool {
+        self.conflict
+            .is_some_and(|conflict| self.value.is_none() || conflict.theirs.is_none())
+    }
+}
+
+struct Markers<'a> {
+    conflicts: HashMap<&'a str, &'a Conflict>,
+    // Paths with a conflict below them, which are written member by member.
+    above: HashSet<&'a str>,
+    ours: &'a str,
+    theirs: &'a str,
+    out: String,
+}
+
+impl Markers<'_> {
+    fn value(&mut self, value: &serde_json::Value, path: &str, indent: &str) {
+        let (open, close, members) = match value {
+            serde_json::Value::Object(map) if self.above.contains(path) => {
+                let members: Vec<(String, Option<&serde_json::Value>)> = map
+                    .iter()
+                    .map(|(key, value)| (key.clone(), Some(value)))
+                    .collect();
+                ('{', '}', members)
+            }
+            serde_json::Value::Array(vec) if self.above.contains(path) => {
+                let members: Vec<(String, Option<&serde_json::Value>)> = vec
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| (i.to_string(), Some(value)))
+                    .collect();
+                ('[', ']', members)
+            }
+            _ => {
+                self.out.push_str(&pretty(value, indent));
+                return;
+            }
+        };
+        // Members only on their side aren't in the merged document.
+        let mut members = members;
+        let prefix = if path == "/" { "" } else { path };
+        for conflict in self.conflicts.values() {
+            if let Some(token) = conflict
+                .path
+                .strip_prefix(prefix)
+                .and_then(|rest| rest.strip_prefix('/').filter(|token| !token.contains('/')))
+            {
+                let token = crate::path::unescape(token);
+                if !members.iter().any(|(member, _)| *member == token) {
+                    members.push((token, None));
+                }
+            }
+        }
+        if open == '[' {
+            // By index, as the map of conflicts is unordered.
+            members.sort_by_key(|(token, _)| token.parse::<usize>().unwrap_or(usize::MAX));
+        }
+        self.out.push(open);
+        self.out.push('\n');
+        let inner = format!("{}  ", indent);
+        let members: Vec<Member> = members
+            .into_iter()
+            .map(|(token, value)| {
+                let path = format!("{}/{}", prefix, crate::path::escape(&token));
+                let key = if open == '{' {
+                    format!("{}: ", serde_json::Value::String(token))
+                } else {
+                    String::new()
+                };
+                let conflict = self.conflicts.get(path.as_str()).copied();
+                Member {
+                    path,
+                    key,
+                    value,
+                    conflict,
+                }
+            })
+            .collect();
+        // Members after the last one on both sides only exist on one side of a conflict. Whether
+        // the member before them needs a comma depends on which side is kept, so they are
+        // written as a single conflict, and that member is written on both sides of it if it can
+        // be. Otherwise, each side starts with the comma.
+        let tail = members
+            .iter()
+            .rposition(|member| !member.one_sided())
+            .map_or(0, |i| i + 1);
+        let repeat = tail > 0
+            && tail < members.len()
+            && members[tail - 1].conflict.is_none()
+            && !self.above.contains(members[tail - 1].path.as_str());
+        let end = if repeat { tail - 1 } else { tail };
+        for (i, member) in members[..end].iter().enumerate() {
+            let comma = if i + 1 < tail { "," } else { "" };
+            match member.conflict {
+                Some(conflict) => self.conflict(conflict, &inner, member.value, &member.key, comma),
+                None => {
+                    self.out.push_str(&inner);
+                    self.out.push_str(&member.key);
+                    if let Some(value) = member.value {
+                        self.value(value, &member.path, &inner);
+                    }
+                    self.out.push_str(comma);
+                    self.out.push('\n');
+                }
+            }
+        }
+        if tail < members.len() {
+            let side = |theirs: bool| {
+                let values: Vec<(&str, &serde_json::Value)> = members[end..]
+                    .iter()
+                    .filter_map(|member| {
+                        let value = match member.conflict {
+                            Some(conflict) if theirs => conflict.theirs.as_ref(),
+                            _ => member.value,
+                        };
+                        value.map(|value| (member.key.as_str(), value))
+                    })
+                    .collect();
+                let mut ret = String::new();
+                for (j, (key, value)) in values.iter().enumerate() {
+                    let lead = if j == 0 && end > 0 && !repeat {
+                        ", "
+                    } else {
+                        ""
+                    };
+                    let comma = if j + 1 < values.len() { "," } else { "" };
+                    ret.push_str(&format!(
+                        "{}{}{}{}{}\n",
+                        inner,
+                        lead,
+                        key,
+                        pretty(value, &inner),
+                        comma
+                    ));
+                }
+                ret
+            };
+            let (ours, theirs) = (side(false), side(true));
+            self.hunk(&ours, &theirs);
+        }
+        self.out.push_str(indent);
+        self.out.push(close);
+    }
+
+    fn conflict(
+        &mut self,
+        conflict: &Conflict,
+        indent: &str,
+        ours: Option<&serde_json::Value>,
+        key: &str,
+        comma: &str,
+    ) {
+        let side = |value: Option<&serde_json::Value>| match value {
+            Some(value) => format!("{}{}{}{}\n", indent, key, pretty(value, indent), comma),
+            None => String::new(),
+        };
+        self.hunk(&side(ours), &side(conflict.theirs.as_ref()));
+    }
+
+    fn hunk(&mut self, ours: &str, theirs: &str) {
+        self.out.push_str(&format!(
+            "<<<<<<< {}\n{}=======\n{}>>>>>>> {}\n",
+            self.ours, ours, theirs, self.theirs
+        ));
+    }
+}
+
+fn pretty(value: &serde_json::Value, indent: &str) -> String {
+    let pretty = serde_json::to_string_pretty(value).expect("values serialize");
+    pretty.replace('\n', &format!("\n{}", indent))
+}
+
+fn get<'a>(document: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    // `compare` reports a change to the whole document at "/".
+    document.pointer(if path == "/" { "" } else { path })
+}
+
+// Each path above `path`, from the whole document down.
+fn ancestors(path: &str) -> Vec<&str> {
+    let mut ret = vec!["/"];
+    ret.extend(path.match_indices('/').skip(1).map(|(i, _)| &path[..i]));
+    ret
+}
+
+// The index each patch touches in each array above it, by the array's path.
+fn array_indices<'a>(
+    base: &serde_json::Value,
+    patches: &'a [Patch],
+) -> HashMap<&'a str, HashSet<usize>> {
+    let mut ret: HashMap<&str, HashSet<usize>> = HashMap::new();
+    for patch in patches.iter().filter(|p| p.op != JsonPatchOp::Reorder) {
+        for ancestor in ancestors(&patch.path) {
+            if !matches!(get(base, ancestor), Some(serde_json::Value::Array(_))) {
+                continue;
+            }
+            let prefix = if ancestor == "/" { "" } else { ancestor };
+            let token = patch.path[prefix.len() + 1..].split('/').next();
+            if let Some(index) = token.and_then(|token| token.parse().ok()) {
+                ret.entry(ancestor).or_default().insert(index);
+            }
+        }
+    }
+    ret
+}
+
+// Arrays are compared index by index, so removing or inserting an element before the end shows
+// up as changes to every element after it. Where both sides changed an array differently, their
+// changes can only be combined if neither side did that: each side either keeps the array's
+// length or only changes elements past the end of the shorter of it and the base.
+fn shifted_arrays<'a>(
+    base: &serde_json::Value,
+    ours: &serde_json::Value,
+    theirs: &serde_json::Value,
+    our_patches: &'a [Patch],
+    their_patches: &[Patch],
+) -> HashSet<&'a str> {
+    let their_indices = array_indices(base, their_patches);
+    let shifted = |path: &str, indices: &HashSet<usize>, side: &serde_json::Value| {
+        let (Some(serde_json::Value::Array(old)), Some(serde_json::Value::Array(new))) =
+            (get(base, path), get(side, path))
+        else {
+            return false;
+        };
+        old.len() != new.len() && indices.iter().any(|&i| i < old.len().min(new.len()))
+    };
+    array_indices(base, our_patches)
+        .into_iter()
+        .filter(|(path, our_indices)| {
+            their_indices.get(path).is_some_and(|their_indices| {
+                get(ours, path) != get(theirs, path)
+                    && (shifted(path, our_indices, ours) || shifted(path, their_indices, theirs))
+            })
+        })
+        .map(|(path, _)| path)
+        .collect()
+}
+
+pub fn merge(
+    base: &serde_json::Value,
+    ours: &serde_json::Value,
+    theirs: &serde_json::Value,
+    options: &CompareOptions,
+) -> Merge {
+    let mut our_patches: Vec<Patch> = Vec::new();
+    compare_with(base, ours, &mut our_patches, options);
+    let mut their_patches: Vec<Patch> = Vec::new();
+    compare_with(base, theirs, &mut their_patches, options);
+
+    // The patches from one comparison never touch a path below another, so a change of theirs
+    // overlaps ours if it is at the same path, below a path we changed, or above one.
+    let ours_by_path: HashMap<&str, &Patch> = our_patches
+        .iter()
+        .map(|patch| (patch.path.as_str(), patch))
+        .collect();
+    let above_ours: HashSet<&str> = our_patches
+        .iter()
+        .filter(|patch| patch.path != "/")
+        .flat_map(|patch| ancestors(&patch.path))
+        .collect();
+    let shifted = shifted_arrays(base, ours, theirs, &our_patches, &their_patches);
+
+    let mut value = ours.clone();
+    let mut conflicts: Vec<Conflict> = Vec::new();
+    let mut conflicting: HashSet<String> = HashSet::new();
+    for patch in their_patches
+        .iter()
+        .filter(|p| p.op != JsonPatchOp::Reorder)
+    {
+        let path = patch.path.as_str();
+        let conflict = match ours_by_path.get(path) {
+            // The whole array conflicts, from the highest one.
+            _ if ancestors(path)
+                .iter()
+                .any(|ancestor| shifted.contains(ancestor)) =>
+            {
+                ancestors(path)
+                    .into_iter()
+                    .find(|ancestor| shifted.contains(ancestor))
+            }
+            Some(our) => {
+                let same = our.op == patch.op
+                    && match (&our.value, &patch.value) {
+                        (Some(our), Some(their)) => equal(our, their, options),
+                        (None, None) => true,
+                        _ => false,
+                    };
+                if same {
+                    // Already in our side.
+                    continue;
+                }
+                Some(path)
+            }
+            None if above_ours.contains(path) => Some(path),
+            None => ancestors(path)
+                .into_iter()
+                .find(|ancestor| path != "/" && ours_by_path.contains_key(ancestor)),
+        };
+        let conflict = match conflict {
+            Some(conflict) => Some(conflict),
+            // Their change can still fail to apply, e.g. adding past the end of an array we
+            // shortened.
+            None => apply_one(&mut value, patch).err().map(|_| path),
+        };
+        if let Some(path) = conflict {
+            if conflicting.insert(path.to_string()) {
+                conflicts.push(Conflict {
+                    path: path.to_string(),
+                    base: get(base, path).cloned(),
+                    ours: get(ours, path).cloned(),
+                    theirs: get(theirs, path).cloned(),
+                });
+            }
+        }
+    }
+    Merge { value, conflicts }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::merge::{merge, Conflict};
+    use crate::CompareOptions;
+
+    #[test]
+    fn test_merge() {
+        let base = serde_json::json!({
+            "name": "app",
+            "version": "1.0",
+            "dependencies": {"a": "1", "b": "1"},
+            "ports": [80, 443],
+            "debug": false
+        });
+        let ours = serde_json::json!({
+            "name": "app",
+            "version": "1.1",
+            "dependencies": {"a": "2", "b": "1"},
+            "ports": [80, 443, 8080],
+            "debug": true
+        });
+        let theirs = serde_json::json!({
+            "name": "application",
+            "version": "1.2",
+            "dependencies": {"a": "2", "b": "1", "c": "1"},
+            "ports": [80],
+            "debug": true
+        });
+        let merge = merge(&base, &ours, &theirs, &CompareOptions::default());
+        let expected = serde_json::json!({
+            "name": "application",
+            "version": "1.1",
+            "dependencies": {"a": "2", "b": "1", "c": "1"},
+            "ports": [80, 8080],
+            "debug": true
+        });
+        assert_eq!(expected, merge.value);
+        let expected = vec![Conflict {
+            path: "/version".to_string(),
+            base: Some(serde_json::json!("1.0")),
+            ours: Some(serde_json::json!("1.1")),
+            theirs: Some(serde_json::json!("1.2")),
+        }];
+        assert_eq!(expected, merge.conflicts);
+    }
+
+    #[test]
+    fn test_merge_same_changes() {
+        let base = serde_json::json!({"a": 1, "b": [1, 2]});
+        let ours = serde_json::json!({"a": 2, "b": [1, 2, 3]});
+        let merge = merge(&base, &ours, &ours, &CompareOptions::default());
+        assert_eq!(ours, merge.value);
+        assert!(merge.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_merge_nested_conflicts() {
+        let base = serde_json::json!({"a": {"b": 1, "c": 2}, "d": [1, 2, 3]});
+        let ours = serde_json::json!({"a": "replaced", "d": [1]});
+        let theirs = serde_json::json!({"a": {"b": 3, "c": 4}, "d": [1, 2, 3, 4]});
+        let merge = merge(&base, &ours, &theirs, &CompareOptions::default());
+        assert_eq!(ours, merge.value);
+        let paths: Vec<&str> = merge.conflicts.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(vec!["/a", "/d/3"], paths);
+        assert_eq!(
+            serde_json::json!({"path": "/d/3", "theirs": 4}),
+            merge.conflicts[1].json()
+        );
+    }
+
+    #[test]
+    fn test_merge_shifted_arrays() {
+        // Theirs removed the first element, which compares as changes to every element.
+        let base = serde_json::json!({"a": ["a", "b", "c"], "b": 1});
+        let ours = serde_json::json!({"a": ["a", "b"], "b": 1});
+        let theirs = serde_json::json!({"a": ["b", "c"], "b": 2});
+        let merge = merge(&base, &ours, &theirs, &CompareOptions::default());
+        assert_eq!(serde_json::json!({"a": ["a", "b"], "b": 2}), merge.value);
+        let expected = vec![Conflict {
+            path: "/a".to_string(),
+            base: Some(serde_json::json!(["a", "b", "c"])),
+            ours: Some(serde_json::json!(["a", "b"])),
+            theirs: Some(serde_json::json!(["b", "c"])),
+        }];
+        assert_eq!(expected, merge.conflicts);
+        let merge = super::merge(
+            &base["a"],
+            &ours["a"],
+            &theirs["a"],
+            &CompareOptions::default(),
+        );
+        let paths: Vec<&str> = merge.conflicts.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(vec!["/"], paths);
+        // Changes to the elements of an array that keeps its length, and appends, still merge.
+        let base = serde_json::json!([{"x": 1}, 2, 3]);
+        let ours = serde_json::json!([{"x": 2}, 2, 4]);
+        let theirs = serde_json::json!([{"x": 1}, 2, 3, 5]);
+        let merge = super::merge(&base, &ours, &theirs, &CompareOptions::default());
+        assert_eq!(serde_json::json!([{"x": 2}, 2, 4, 5]), merge.value);
+        assert!(merge.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_conflict_markers() {
+        let base = serde_json::json!({"a": {"b": 1, "c": [1, 2]}, "d": 1});
+        let ours = serde_json::json!({"a": {"b": 2, "c": [1]}, "d": 1});
+        let theirs = serde_json::json!({"a": {"b": 3, "c": [1, 2, 3]}, "d": 2});
+        let merge = merge(&base, &ours, &theirs, &CompareOptions::default());
+        let expected = r#"{
+  "a": {
+<<<<<<< ours
+    "b": 2,
+=======
+    "b": 3,
+>>>>>>> theirs
+    "c": [
+<<<<<<< ours
+      1
+=======
+      1,
+      3
+>>>>>>> theirs
+    ]
+  },
+  "d": 2
+}
+"#;
+        assert_eq!(expected, merge.conflict_markers("ours", "theirs"));
+        // Without conflicts, the merged document is pretty-printed.
+        let merge = super::merge(&base, &ours, &ours, &CompareOptions::default());
+        let expected = serde_json::to_string_pretty(&ours).unwrap() + "\n";
+        assert_eq!(expected, merge.conflict_markers("ours", "theirs"));
+    }
+
+    // Keeping either side of every conflict leaves valid JSON, and keeping ours leaves the merged
+    // document.
+    #[test]
+    fn test_conflict_markers_resolve() {
+        let cases = [
+            (
+                serde_json::json!({"a": 1, "b": 1}),
+                serde_json::json!({"a": 1}),
+                serde_json::json!({"a": 1, "b": 2}),
+            ),
+            (
+                serde_json::json!({"a": {"b": 1}, "z": 1}),
+                serde_json::json!({"a": {"b": 2}}),
+                serde_json::json!({"a": {"b": 3}, "z": 2}),
+            ),
+            (
+                serde_json::json!([1, 2]),
+                serde_json::json!([]),
+                serde_json::json!([1, 2, 3, 4]),
+            ),
+            (
+                serde_json::json!({"a": 1, "b": 1, "c": 1}),
+                serde_json::json!({"a": 1, "b": 2}),
+                serde_json::json!({"a": 1, "c": 2}),
+            ),
+        ];
+        let resolve = |markers: &str, ours: bool| {
+            let mut ret = String::new();
+            let mut keep = true;
+            for line in markers.lines() {
+                if line.starts_with("<<<<<<< ") {
+                    keep = ours;
+                } else if line == "=======" {
+                    keep = !ours;
+                } else if line.starts_with(">>>>>>> ") {
+                    keep = true;
+                } else if keep {
+                    ret.push_str(line);
+                    ret.push('\n');
+                }
+            }
+            serde_json::from_str::<serde_json::Value>(&ret)
+                .unwrap_or_else(|error| panic!("{}: {}", error, markers))
+        };
+        for (base, ours, theirs) in cases {
+            let merge = merge(&base, &ours, &theirs, &CompareOptions::default());
+            assert!(!merge.conflicts.is_empty());
+            let markers = merge.conflict_markers("ours", "theirs");
+            assert_eq!(merge.value, resolve(&markers, true));
+            resolve(&markers, false);
+        }
+    }
+}
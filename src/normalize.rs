@@ -0,0 +1,356 @@
+// Normalizes values before they are compared, so that differences that don't matter (the case of
+// a string, a time zone, a number quoted as a string) aren't reported. Each rule applies to the
+// whole document, or to a path and everything below it. The documents are compared as
+// normalized copies, but the patches have the original values.
+
+use crate::path::{self, EMBEDDED};
+use crate::{compare_documents, CompareOptions, JsonPatchOp, Patch};
+use clap::ValueEnum;
+use std::str::FromStr;
+
+// Rules are applied in this order, e.g. strings are trimmed before they are parsed as numbers.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum Normalization {
+    /// Ignore whitespace at the start and end of strings
+    Trim,
+    /// Compare strings case-insensitively
+    Case,
+    /// Ignore a trailing slash in strings, e.g. in URLs
+    TrailingSlash,
+    /// Compare RFC 3339 timestamps by the instant they represent, regardless of time zone
+    Timestamps,
+    /// Compare the strings "true" and "false" (in any case) as booleans
+    Booleans,
+    /// Compare strings containing JSON numbers as numbers
+    Numbers,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub normalization: Normalization,
+    // A JSON Pointer in which `*` matches any key or index, or "" for the whole document.
+    pub path: String,
+}
+
+// RULE or RULE:PATH, as on the command line.
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<Rule, String> {
+        let (name, path) = rule.split_once(':').unwrap_or((rule, ""));
+        let normalization = Normalization::from_str(name, true).map_err(|_| {
+            let names: Vec<String> = Normalization::value_variants()
+                .iter()
+                .filter_map(|n| n.to_possible_value())
+                .map(|value| value.get_name().to_string())
+                .collect();
+            format!("unknown rule {} (expected {})", name, names.join(", "))
+        })?;
+        Ok(Rule {
+            normalization,
+            path: path::parse_pattern(path)?,
+        })
+    }
+}
+
+pub(crate) fn compare_with(
+    left: &serde_json::Value,
+    right: &serde_json::Value,
+    patches: &mut Vec<Patch>,
+    options: &CompareOptions,
+) {
+    let (mut normal_left, mut normal_right) = (left.clone(), right.clone());
+    normalize(&mut normal_left, &options.normalize);
+    normalize(&mut normal_right, &options.normalize);
+    let start = patches.len();
+    compare_documents(&normal_left, &normal_right, patches, options);
+    for patch in &mut patches[start..] {
+        if patch.op == JsonPatchOp::Reorder {
+            continue;
+        }
+        if patch.value.is_some() {
+            patch.value = lookup(right, &patch.path).or(patch.value.take());
+        }
+        if patch.old_value.is_some() {
+            patch.old_value = lookup(left, &patch.path).or(patch.old_value.take());
+        }
+    }
+}
+
+pub fn normalize(document: &mut serde_json::Value, rules: &[Rule]) {
+    walk(document, "", rules, &[]);
+}
+
+fn walk(value: &mut serde_json::Value, pointer: &str, rules: &[Rule], inherited: &[Normalization]) {
+    let mut active = inherited.to_vec();
+    for rule in rules {
+        if !active.contains(&rule.normalization) && path::matches(&rule.path, pointer) {
+            active.push(rule.normalization);
+        }
+    }
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                let pointer = format!("{}/{}", pointer, path::escape(key));
+                walk(child, &pointer, rules, &active);
+            }
+        }
+        serde_json::Value::Array(vec) => {
+            for (i, child) in vec.iter_mut().enumerate() {
+                walk(child, &format!("{}/{}", pointer, i), rules, &active);
+            }
+        }
+        _ => {
+            for normalization in Normalization::value_variants() {
+                if active.contains(normalization) {
+                    apply(*normalization, value);
+                }
+            }
+        }
+    }
+}
+
+fn apply(normalization: Normalization, value: &mut serde_json::Value) {
+    let serde_json::Value::String(string) = value else {
+        return;
+    };
+    match normalization {
+        Normalization::Trim => {
+            if string.trim().len() != string.len() {
+                *string = string.trim().to_string();
+            }
+        }
+        Normalization::Case => *string = string.to_lowercase(),
+        Normalization::TrailingSlash => {
+            if string.len() > 1 && string.ends_with('/') {
+                string.pop();
+            }
+        }
+        Normalization::Timestamps => {
+            if let Some(timestamp) = timestamp(string) {
+                *string = timestamp;
+            }
+        }
+        Normalization::Booleans => {
+            if string.eq_ignore_ascii_case("true") || string.eq_ignore_ascii_case("false") {
+                *value = serde_json::Value::Bool(string.eq_ignore_ascii_case("true"));
+            }
+        }
+        Normalization::Numbers => {
+            if let Ok(number) = serde_json::Number::from_str(string) {
+                *value = serde_json::Value::Number(number);
+            }
+        }
+    }
+}
+
+// The value at a path in a document, including inside embedded JSON.
+fn lookup(document: &serde_json::Value, pointer: &str) -> Option<serde_json::Value> {
+    let mut pointers = pointer.split(EMBEDDED);
+    // `compare` reports a change to the whole document at "/".
+    let first = match pointers.next() {
+        Some("/") | None => "",
+        Some(first) => first,
+    };
+    let mut value = document.pointer(first)?.clone();
+    for pointer in pointers {
+        let embedded: serde_json::Value = serde_json::from_str(value.as_str()?).ok()?;
+        value = embedded.pointer(pointer)?.clone();
+    }
+    Some(value)
+}
+
+// An RFC 3339 timestamp in UTC, without trailing zeros in its fraction of a second, e.g.
+// "2024-01-01T01:30:00.50+01:00" becomes "2024-01-01T00:30:00.5Z".
+fn timestamp(text: &str) -> Option<String> {
+    let number = |start: usize, len: usize| -> Option<i64> {
+        let digits = text.get(start..start + len)?;
+        if !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok()
+    };
+    let separator = |i: usize, separators: &[u8]| {
+        text.as_bytes()
+            .get(i)
+            .is_some_and(|b| separators.contains(b))
+    };
+    let (year, month, day) = (number(0, 4)?, number(5, 2)?, number(8, 2)?);
+    let (hour, minute, second) = (number(11, 2)?, number(14, 2)?, number(17, 2)?);
+    if !(separator(4, b"-")
+        && separator(7, b"-")
+        && separator(10, b"Tt ")
+        && separator(13, b":")
+        && separator(16, b":"))
+    {
+        return None;
+    }
+    let rest = &text[19..];
+    let (fraction, zone) = match rest.strip_prefix('.') {
+        Some(rest) => {
+            let digits = rest.bytes().take_while(|b| b.is_ascii_digit()).count();
+            if digits == 0 {
+                return None;
+            }
+            (rest[..digits].trim_end_matches('0'), &rest[digits..])
+        }
+        None => ("", rest),
+    };
+    let offset = match zone {
+        "Z" | "z" => 0,
+        _ => {
+            let sign = match zone.as_bytes().first()? {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let start = text.len() - zone.len();
+            if zone.len() != 6 || !separator(start + 3, b":") {
+                return None;
+            }
+            sign * (number(start + 1, 2)? * 60 + number(start + 4, 2)?)
+        }
+    };
+    let days = days_from_civil(year, month, day);
+    if !(1..=12).contains(&month) || civil_from_days(days) != (year, month, day) {
+        return None;
+    }
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let minutes = days * 1440 + hour * 60 + minute - offset;
+    let (year, month, day) = civil_from_days(minutes.div_euclid(1440));
+    let minutes = minutes.rem_euclid(1440);
+    let fraction = if fraction.is_empty() {
+        String::new()
+    } else {
+        format!(".{}", fraction)
+    };
+    Some(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}Z",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60,
+        second,
+        fraction
+    ))
+}
+
+// Days since 1970-01-01 in the proleptic Gregorian calendar, and back, after Howard Hinnant's
+// `days_from_civil` and `civil_from_days`.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::normalize::{normalize, timestamp, Normalization, Rule};
+    use crate::{compare_with, CompareOptions, Patch};
+
+    #[test]
+    fn test_normalize() {
+        let mut document = serde_json::json!({
+            "name": " Web ",
+            "url": "https://example.com/",
+            "users": [{"email": "A@Example.com", "admin": "TRUE", "id": " 42 "}]
+        });
+        let rules: Vec<Rule> = [
+            "trim",
+            "trailing-slash:/url",
+            "case:/users/*/email",
+            "booleans:/users",
+            "numbers:/users/*/id",
+        ]
+        .iter()
+        .map(|rule| rule.parse().unwrap())
+        .collect();
+        normalize(&mut document, &rules);
+        let expected = serde_json::json!({
+            "name": "Web",
+            "url": "https://example.com",
+            "users": [{"email": "a@example.com", "admin": true, "id": 42}]
+        });
+        assert_eq!(expected, document);
+        let error = "upper:/a".parse::<Rule>().unwrap_err();
+        assert!(error.starts_with("unknown rule upper (expected trim, case, "));
+        assert_eq!(
+            Err("invalid path users (expected \"\" or a JSON Pointer starting with /)".to_string()),
+            "case:users".parse::<Rule>()
+        );
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let cases = [
+            ("2024-01-01T01:30:00+01:00", Some("2024-01-01T00:30:00Z")),
+            (
+                "2024-03-01t00:15:00.500-00:30",
+                Some("2024-03-01T00:45:00.5Z"),
+            ),
+            (
+                "2024-03-01 00:15:00.000+00:30",
+                Some("2024-02-29T23:45:00Z"),
+            ),
+            ("2023-12-31T23:59:59Z", Some("2023-12-31T23:59:59Z")),
+            ("2023-02-29T00:00:00Z", None),
+            ("2024-01-01T24:00:00Z", None),
+            ("2024-01-01T00:00:00", None),
+            ("2024-01-01", None),
+            ("not a timestamp", None),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(expected.map(str::to_string), timestamp(text), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_compare_normalized() {
+        let left = serde_json::json!({"at": "2024-01-01T01:00:00+01:00", "flag": "TRUE", "n": "1"});
+        let right = serde_json::json!({"at": "2024-01-01T00:00:00Z", "flag": true, "n": "2"});
+        let options = CompareOptions {
+            normalize: vec![
+                Rule {
+                    normalization: Normalization::Timestamps,
+                    path: "/at".to_string(),
+                },
+                Rule {
+                    normalization: Normalization::Booleans,
+                    path: String::new(),
+                },
+                Rule {
+                    normalization: Normalization::Numbers,
+                    path: String::new(),
+                },
+            ],
+            ..Default::default()
+        };
+        let mut patches: Vec<Patch> = Vec::new();
+        compare_with(&left, &right, &mut patches, &options);
+        // The patch has the original values.
+        assert_eq!(1, patches.len());
+        assert_eq!("/n", patches[0].path);
+        assert_eq!(Some(serde_json::json!("2")), patches[0].value);
+        assert_eq!(Some(serde_json::json!("1")), patches[0].old_value);
+    }
+}
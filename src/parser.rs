@@ -0,0 +1,605 @@
+// A JSON parser that keeps track of where it is in the input. serde_json silently collapses
+// duplicate object keys (the last one wins), which can hide real differences between two
+// files, so this parser records every duplicate it sees. Strings and numbers are still
+// decoded by serde_json, so escapes and arbitrary precision numbers behave identically.
+
+use serde::de::{Deserialize, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufRead, Bytes};
+
+// The same limit serde_json uses, so deeply nested input fails cleanly instead of overflowing
+// the stack.
+const RECURSION_LIMIT: usize = 128;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {} column {}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DuplicateKey {
+    pub path: String,
+    pub position: Position,
+}
+
+#[derive(Debug)]
+pub struct Error {
+    message: String,
+    position: Position,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.message, self.position)
+    }
+}
+
+// Where each value starts, keyed by JSON Pointer. The document itself is at "".
+pub type Locations = HashMap<String, Position>;
+
+#[derive(Debug)]
+pub struct Document {
+    pub value: serde_json::Value,
+    pub duplicates: Vec<DuplicateKey>,
+    pub locations: Locations,
+}
+
+// Recording the location of every value costs a path per value, so it is opt-in. Without
+// locations, serde_json's faster parser builds the value, unless the document has duplicate keys
+// (whose positions only this parser knows) or doesn't parse (so errors read the same either way).
+pub fn parse<R: BufRead>(mut reader: R, track_locations: bool) -> Result<Document, Error> {
+    if track_locations {
+        return parse_tokens(reader, true);
+    }
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).map_err(|error| Error {
+        message: error.to_string(),
+        position: Position { line: 1, column: 1 },
+    })?;
+    if !has_duplicates(&bytes) {
+        if let Ok(value) = serde_json::from_slice(&bytes) {
+            return Ok(Document {
+                value,
+                duplicates: Vec::new(),
+                locations: Locations::new(),
+            });
+        }
+    }
+    parse_tokens(bytes.as_slice(), false)
+}
+
+fn parse_tokens<R: BufRead>(reader: R, track_locations: bool) -> Result<Document, Error> {
+    let mut parser = Parser::new(reader, track_locations);
+    let mut path = String::new();
+    let value = parser.parse_value(&mut path, 0)?;
+    parser.end()?;
+    Ok(Document {
+        value,
+        duplicates: parser.duplicates,
+        locations: parser.locations.unwrap_or_default(),
+    })
+}
+
+// Whether any object in `bytes` has the same key twice, or `bytes` isn't a JSON document. Only
+// keys are kept, borrowed from the input where they have no escapes.
+fn has_duplicates(bytes: &[u8]) -> bool {
+    struct Key<'de>(Cow<'de, str>);
+
+    impl<'de> Deserialize<'de> for Key<'de> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct KeyVisitor;
+
+            impl<'de> Visitor<'de> for KeyVisitor {
+                type Value = Key<'de>;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("a string")
+                }
+
+                fn visit_borrowed_str<E>(self, key: &'de str) -> Result<Key<'de>, E> {
+                    Ok(Key(Cow::Borrowed(key)))
+                }
+
+                fn visit_str<E>(self, key: &str) -> Result<Key<'de>, E> {
+                    Ok(Key(Cow::Owned(key.to_string())))
+                }
+            }
+
+            deserializer.deserialize_str(KeyVisitor)
+        }
+    }
+
+    // Visits a value, returning whether it has a duplicate key anywhere inside it.
+    struct Scan;
+
+    impl<'de> DeserializeSeed<'de> for Scan {
+        type Value = bool;
+
+        fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<bool, D::Error> {
+            deserializer.deserialize_any(self)
+        }
+    }
+
+    impl<'de> Visitor<'de> for Scan {
+        type Value = bool;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a JSON value")
+        }
+
+        fn visit_bool<E>(self, _: bool) -> Result<bool, E> {
+            Ok(false)
+        }
+
+        fn visit_i64<E>(self, _: i64) -> Result<bool, E> {
+            Ok(false)
+        }
+
+        fn visit_u64<E>(self, _: u64) -> Result<bool, E> {
+            Ok(false)
+        }
+
+        fn visit_f64<E>(self, _: f64) -> Result<bool, E> {
+            Ok(false)
+        }
+
+        fn visit_str<E>(self, _: &str) -> Result<bool, E> {
+            Ok(false)
+        }
+
+        fn visit_unit<E>(self) -> Result<bool, E> {
+            Ok(false)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<bool, A::Error> {
+            let mut ret = false;
+            while let Some(duplicates) = seq.next_element_seed(Scan)? {
+                ret |= duplicates;
+            }
+            Ok(ret)
+        }
+
+        // With arbitrary_precision, serde_json passes numbers as single-member maps, which
+        // can't have duplicates.
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<bool, A::Error> {
+            let mut keys: Vec<Cow<'de, str>> = Vec::new();
+            let mut ret = false;
+            while let Some(Key(key)) = map.next_key()? {
+                keys.push(key);
+                ret |= map.next_value_seed(Scan)?;
+            }
+            keys.sort_unstable();
+            Ok(ret || keys.windows(2).any(|pair| pair[0] == pair[1]))
+        }
+    }
+
+    let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+    Scan.deserialize(&mut deserializer)
+        .and_then(|duplicates| deserializer.end().map(|_| duplicates))
+        .unwrap_or(true)
+}
+
+// The beginning of a value. Containers are opened but not read, so that a caller can walk
+// them member by member instead of building the whole tree.
+pub enum Start {
+    Object,
+    Array,
+    Scalar(serde_json::Value),
+}
+
+pub struct Parser<R: BufRead> {
+    bytes: Bytes<R>,
+    peeked: Option<u8>,
+    position: Position,
+    duplicates: Vec<DuplicateKey>,
+    locations: Option<Locations>,
+}
+
+impl<R: BufRead> Parser<R> {
+    pub fn new(reader: R, track_locations: bool) -> Parser<R> {
+        Parser {
+            bytes: reader.bytes(),
+            peeked: None,
+            position: Position { line: 1, column: 1 },
+            duplicates: Vec::new(),
+            locations: track_locations.then(Locations::new),
+        }
+    }
+
+    fn error(&self, message: &str) -> Error {
+        Error {
+            message: message.to_string(),
+            position: self.position,
+        }
+    }
+
+    fn io_error(&self, error: io::Error) -> Error {
+        self.error(&error.to_string())
+    }
+
+    fn peek(&mut self) -> Result<Option<u8>, Error> {
+        if self.peeked.is_none() {
+            self.peeked = match self.bytes.next() {
+                Some(Ok(byte)) => Some(byte),
+                Some(Err(error)) => return Err(self.io_error(error)),
+                None => None,
+            };
+        }
+        Ok(self.peeked)
+    }
+
+    fn next(&mut self) -> Result<Option<u8>, Error> {
+        let byte = self.peek()?;
+        self.peeked = None;
+        match byte {
+            Some(b'\n') => {
+                self.position.line += 1;
+                self.position.column = 1;
+            }
+            // Count characters rather than bytes, so columns match what editors display.
+            Some(byte) if byte & 0xc0 != 0x80 => self.position.column += 1,
+            _ => {}
+        }
+        Ok(byte)
+    }
+
+    fn skip_whitespace(&mut self) -> Result<Option<u8>, Error> {
+        while let Some(byte) = self.peek()? {
+            if !matches!(byte, b' ' | b'\t' | b'\n' | b'\r') {
+                return Ok(Some(byte));
+            }
+            self.next()?;
+        }
+        Ok(None)
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), Error> {
+        match self.skip_whitespace()? {
+            Some(byte) if byte == expected => {
+                self.next()?;
+                Ok(())
+            }
+            Some(_) => Err(self.error(&format!("expected `{}`", expected as char))),
+            None => Err(self.error("EOF while parsing a value")),
+        }
+    }
+
+    fn expect_literal(&mut self, literal: &str) -> Result<(), Error> {
+        for expected in literal.bytes() {
+            if self.peek()? != Some(expected) {
+                return Err(self.error(&format!("expected `{}`", literal)));
+            }
+            self.next()?;
+        }
+        Ok(())
+    }
+
+    // Fails if there is anything but whitespace after the document.
+    pub fn end(&mut self) -> Result<(), Error> {
+        if self.skip_whitespace()?.is_some() {
+            return Err(self.error("trailing characters"));
+        }
+        Ok(())
+    }
+
+    pub fn into_duplicates(self) -> Vec<DuplicateKey> {
+        self.duplicates
+    }
+
+    pub fn duplicate(&mut self, path: &str, position: Position) {
+        self.duplicates.push(DuplicateKey {
+            path: path.to_string(),
+            position,
+        });
+    }
+
+    pub fn start(&mut self, path: &str, depth: usize) -> Result<Start, Error> {
+        if depth > RECURSION_LIMIT {
+            return Err(self.error("recursion limit exceeded"));
+        }
+        let next = self.skip_whitespace()?;
+        if let Some(locations) = &mut self.locations {
+            locations.insert(path.to_string(), self.position);
+        }
+        match next {
+            Some(b'{') => {
+                self.next()?;
+                Ok(Start::Object)
+            }
+            Some(b'[') => {
+                self.next()?;
+                Ok(Start::Array)
+            }
+            Some(b'"') => Ok(Start::Scalar(serde_json::Value::String(
+                self.parse_string()?,
+            ))),
+            Some(b'-' | b'0'..=b'9') => Ok(Start::Scalar(serde_json::Value::Number(
+                self.parse_number()?,
+            ))),
+            Some(b't') => self
+                .expect_literal("true")
+                .map(|_| Start::Scalar(serde_json::Value::Bool(true))),
+            Some(b'f') => self
+                .expect_literal("false")
+                .map(|_| Start::Scalar(serde_json::Value::Bool(false))),
+            Some(b'n') => self
+                .expect_literal("null")
+                .map(|_| Start::Scalar(serde_json::Value::Null)),
+            Some(_) => Err(self.error("expected value")),
+            None => Err(self.error("EOF while parsing a value")),
+        }
+    }
+
+    // The next key of an object opened by `start`, or None at the end of the object. `first`
+    // is whether this is the first member, which is not preceded by a comma.
+    pub fn next_key(&mut self, first: bool) -> Result<Option<(String, Position)>, Error> {
+        if first {
+            if self.skip_whitespace()? == Some(b'}') {
+                self.next()?;
+                return Ok(None);
+            }
+        } else {
+            match self.skip_whitespace()? {
+                Some(b',') => {
+                    self.next()?;
+                }
+                Some(b'}') => {
+                    self.next()?;
+                    return Ok(None);
+                }
+                Some(_) => return Err(self.error("expected `,` or `}`")),
+                None => return Err(self.error("EOF while parsing an object")),
+            }
+        }
+        if self.skip_whitespace()? != Some(b'"') {
+            return Err(self.error("key must be a string"));
+        }
+        let position = self.position;
+        let key = self.parse_string()?;
+        self.expect(b':')?;
+        Ok(Some((key, position)))
+    }
+
+    // Whether an array opened by `start` has another element.
+    pub fn next_element(&mut self, first: bool) -> Result<bool, Error> {
+        if first {
+            if self.skip_whitespace()? == Some(b']') {
+                self.next()?;
+                return Ok(false);
+            }
+            return Ok(true);
+        }
+        match self.skip_whitespace()? {
+            Some(b',') => {
+                self.next()?;
+                Ok(true)
+            }
+            Some(b']') => {
+                self.next()?;
+                Ok(false)
+            }
+            Some(_) => Err(self.error("expected `,` or `]`")),
+            None => Err(self.error("EOF while parsing a list")),
+        }
+    }
+
+    // Read the rest of a value opened by `start`.
+    pub fn finish(
+        &mut self,
+        start: Start,
+        path: &mut String,
+        depth: usize,
+    ) -> Result<serde_json::Value, Error> {
+        match start {
+            Start::Object => {
+                let mut map = serde_json::Map::new();
+                self.parse_members(&mut map, true, path, depth)?;
+                Ok(serde_json::Value::Object(map))
+            }
+            Start::Array => {
+                let mut vec = Vec::new();
+                let mut first = true;
+                while self.next_element(first)? {
+                    first = false;
+                    let len = path.len();
+                    path.push_str(&format!("/{}", vec.len()));
+                    vec.push(self.parse_value(path, depth + 1)?);
+                    path.truncate(len);
+                }
+                Ok(serde_json::Value::Array(vec))
+            }
+            Start::Scalar(value) => Ok(value),
+        }
+    }
+
+    pub fn parse_value(
+        &mut self,
+        path: &mut String,
+        depth: usize,
+    ) -> Result<serde_json::Value, Error> {
+        let start = self.start(path, depth)?;
+        self.finish(start, path, depth)
+    }
+
+    // Read the remaining members of an object into `map`.
+    pub fn parse_members(
+        &mut self,
+        map: &mut serde_json::Map<String, serde_json::Value>,
+        first: bool,
+        path: &mut String,
+        depth: usize,
+    ) -> Result<(), Error> {
+        let mut first = first;
+        while let Some((key, position)) = self.next_key(first)? {
+            first = false;
+            let len = path.len();
+            path.push('/');
+            path.push_str(&crate::path::escape(&key));
+            let value = self.parse_value(path, depth + 1)?;
+            if map.insert(key, value).is_some() {
+                self.duplicate(path, position);
+            }
+            path.truncate(len);
+        }
+        Ok(())
+    }
+
+    // Find the end of the string, then let serde_json decode the escapes.
+    fn parse_string(&mut self) -> Result<String, Error> {
+        let position = self.position;
+        let mut raw = vec![b'"'];
+        self.next()?;
+        loop {
+            match self.next()? {
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    raw.push(b'\\');
+                    match self.next()? {
+                        Some(byte) => raw.push(byte),
+                        None => return Err(self.error("EOF while parsing a string")),
+                    }
+                }
+                Some(byte) => raw.push(byte),
+                None => return Err(self.error("EOF while parsing a string")),
+            }
+        }
+        raw.push(b'"');
+        serde_json::from_slice(&raw).map_err(|error| Error {
+            message: strip_serde_position(&error),
+            position,
+        })
+    }
+
+    fn parse_number(&mut self) -> Result<serde_json::Number, Error> {
+        let position = self.position;
+        let mut raw = Vec::new();
+        while let Some(byte @ (b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E')) = self.peek()? {
+            raw.push(byte);
+            self.next()?;
+        }
+        serde_json::from_slice(&raw).map_err(|error| Error {
+            message: strip_serde_position(&error),
+            position,
+        })
+    }
+}
+
+// serde_json only saw a single token, so its own line and column would be misleading.
+fn strip_serde_position(error: &serde_json::Error) -> String {
+    let message = error.to_string();
+    match message.rfind(" at line ") {
+        Some(i) => message[..i].to_string(),
+        None => message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{has_duplicates, parse, DuplicateKey, Locations, Position};
+
+    #[test]
+    fn test_parse_matches_serde_json() {
+        let contents = r#"{
+            "string": "This is a \"string\".\u00e9",
+            "integer": 42,
+            "float": 3.5e-2,
+            "object": {"substring": "This is another string."},
+            "array": ["one", "two", [], {}],
+            "boolean": true,
+            "false": false,
+            "null": null
+        }"#;
+        let document = parse(contents.as_bytes(), false).unwrap();
+        let expected: serde_json::Value = serde_json::from_str(contents).unwrap();
+        assert_eq!(expected, document.value);
+        assert!(document.duplicates.is_empty());
+        assert!(document.locations.is_empty());
+    }
+
+    #[test]
+    fn test_has_duplicates() {
+        assert!(!has_duplicates(
+            br#"{"a": {"a": 1e400}, "b": [{"a": 1}, {"a": 2}]}"#
+        ));
+        assert!(has_duplicates(br#"{"a": 1, "\u0061": 2}"#));
+        assert!(has_duplicates(br#"[1, {"b": {"c": null, "c": null}}]"#));
+        // Input that doesn't parse goes to the parser that reports where.
+        assert!(has_duplicates(br#"{"a": 1,}"#));
+        assert!(has_duplicates(b"1 2"));
+    }
+
+    #[test]
+    fn test_parse_locations() {
+        let contents = "{\n  \"a\": [1, {\"b\": null}],\n  \"é\": \"é\"\n}";
+        let document = parse(contents.as_bytes(), true).unwrap();
+        let expected = Locations::from([
+            ("".to_string(), Position { line: 1, column: 1 }),
+            ("/a".to_string(), Position { line: 2, column: 8 }),
+            ("/a/0".to_string(), Position { line: 2, column: 9 }),
+            (
+                "/a/1".to_string(),
+                Position {
+                    line: 2,
+                    column: 12,
+                },
+            ),
+            (
+                "/a/1/b".to_string(),
+                Position {
+                    line: 2,
+                    column: 18,
+                },
+            ),
+            ("/é".to_string(), Position { line: 3, column: 8 }),
+        ]);
+        assert_eq!(expected, document.locations);
+    }
+
+    #[test]
+    fn test_parse_duplicate_keys() {
+        let contents = "{\n  \"a\": 1,\n  \"b\": {\"c\": [{\"d\": 1, \"d\": 2}]},\n  \"a\": 3\n}";
+        let document = parse(contents.as_bytes(), false).unwrap();
+        assert_eq!(
+            serde_json::json!({"a": 3, "b": {"c": [{"d": 2}]}}),
+            document.value
+        );
+        let expected = vec![
+            DuplicateKey {
+                path: "/b/c/0/d".to_string(),
+                position: Position {
+                    line: 3,
+                    column: 24,
+                },
+            },
+            DuplicateKey {
+                path: "/a".to_string(),
+                position: Position { line: 4, column: 3 },
+            },
+        ];
+        assert_eq!(expected, document.duplicates);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = parse("{\"a\": 1,\n \"b\" 2}".as_bytes(), false).unwrap_err();
+        assert_eq!("expected `:` at line 2 column 6", error.to_string());
+        let error = parse("[1, 2] 3".as_bytes(), false).unwrap_err();
+        assert_eq!("trailing characters at line 1 column 8", error.to_string());
+        let error = parse("[\"\\q\"]".as_bytes(), false).unwrap_err();
+        assert_eq!("invalid escape at line 1 column 2", error.to_string());
+        let error = parse("[".repeat(200).as_bytes(), false).unwrap_err();
+        assert_eq!(
+            "recursion limit exceeded at line 1 column 130",
+            error.to_string()
+        );
+    }
+}
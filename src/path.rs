@@ -0,0 +1,275 @@
+// Paths are JSON Pointers (RFC 6901) internally, and can be displayed in the notations of other
+// tools. A pointer does not say whether "/0" is an array index or an object key, so rendering a
+// path walks the document it points into.
+
+use std::borrow::Cow;
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum PathStyle {
+    /// JSON Pointer, e.g. /spec/containers/0/image
+    Pointer,
+    /// jq, e.g. .spec.containers[0].image
+    Jq,
+    /// JSONPath, e.g. $.spec.containers[0].image
+    Jsonpath,
+    /// Dotted keys, e.g. spec.containers[0].image
+    Dotted,
+}
+
+pub fn escape(key: &str) -> Cow<'_, str> {
+    if key.contains(['~', '/']) {
+        Cow::Owned(key.replace('~', "~0").replace('/', "~1"))
+    } else {
+        Cow::Borrowed(key)
+    }
+}
+
+pub fn unescape(token: &str) -> String {
+    token.replace("~1", "/").replace("~0", "~")
+}
+
+// Whether `pointer` is an RFC 6901 JSON Pointer: "" or tokens each after a "/", in which "~" is
+// only followed by "0" or "1". The paths of changes inside embedded JSON aren't.
+pub fn is_pointer(pointer: &str) -> bool {
+    (pointer.is_empty() || pointer.starts_with('/'))
+        && pointer
+            .split('~')
+            .skip(1)
+            .all(|rest| rest.starts_with(['0', '1']))
+}
+
+// Changes inside JSON embedded in a string are at the string's pointer, then this, then the
+// pointer within the embedded JSON. Escaped keys never contain "~j", so it can't be mistaken for
+// one.
+pub const EMBEDDED: &str = "/~j";
+
+// The pointer to the string that a change inside embedded JSON is in, or `pointer` itself.
+pub fn outside_embedded(pointer: &str) -> &str {
+    pointer.split(EMBEDDED).next().unwrap_or(pointer)
+}
+
+// The pointer to the value a change is at in the parsed documents. `compare` reports a change to
+// the whole document at "/", which is "" everywhere else, and the documents have embedded JSON
+// as a string, so changes inside it are at the string.
+pub fn located(path: &str) -> &str {
+    match outside_embedded(path) {
+        "/" => "",
+        pointer => pointer,
+    }
+}
+
+// Checks a `pattern` given on the command line, which must be "" or start with "/" to be matched.
+pub fn parse_pattern(pattern: &str) -> Result<String, String> {
+    if pattern.is_empty() || pattern.starts_with('/') {
+        Ok(pattern.to_string())
+    } else {
+        Err(format!(
+            "invalid path {} (expected \"\" or a JSON Pointer starting with /)",
+            pattern
+        ))
+    }
+}
+
+// Whether `pointer` matches `pattern`, a JSON Pointer in which a `*` token matches any key or
+// index.
+pub fn matches(pattern: &str, pointer: &str) -> bool {
+    let (pattern, pointer) = (tokens(pattern), tokens(pointer));
+    pattern.len() == pointer.len()
+        && pattern
+            .iter()
+            .zip(&pointer)
+            .all(|(pattern, token)| pattern == "*" || pattern == token)
+}
+
+// `compare` reports a change to the whole document at "/".
+pub fn tokens(pointer: &str) -> Vec<String> {
+    if pointer.is_empty() || pointer == "/" {
+        return Vec::new();
+    }
+    pointer.split('/').skip(1).map(unescape).collect()
+}
+
+fn is_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// The embedded JSON a path goes into is rendered as a separate path, after "#" or, for jq,
+// `fromjson`.
+pub fn render(pointer: &str, style: PathStyle, document: &serde_json::Value) -> String {
+    let mut pointers = pointer.split(EMBEDDED);
+    let first = pointers.next().unwrap_or(pointer);
+    let mut ret = render_one(first, style, document);
+    let mut string = document.pointer(first);
+    let mut embedded: serde_json::Value;
+    for pointer in pointers {
+        // Unparsable JSON is rendered as if it were missing.
+        embedded = string
+            .and_then(|value| value.as_str())
+            .and_then(|string| serde_json::from_str(string).ok())
+            .unwrap_or_default();
+        ret.push_str(if style == PathStyle::Jq {
+            " | fromjson | "
+        } else {
+            "#"
+        });
+        ret.push_str(&render_one(pointer, style, &embedded));
+        string = embedded.pointer(pointer);
+    }
+    ret
+}
+
+fn render_one(pointer: &str, style: PathStyle, document: &serde_json::Value) -> String {
+    if style == PathStyle::Pointer {
+        return pointer.to_string();
+    }
+    let mut ret = match style {
+        PathStyle::Jsonpath => "$".to_string(),
+        _ => String::new(),
+    };
+    let mut value = Some(document);
+    for token in tokens(pointer) {
+        // Where the document isn't known (e.g. when streaming), numbers are taken to be indices.
+        let index = match value {
+            Some(serde_json::Value::Array(_)) | None => token
+                .parse::<usize>()
+                .ok()
+                .filter(|index| index.to_string() == token),
+            _ => None,
+        };
+        value = match (value, index) {
+            (Some(serde_json::Value::Array(vec)), Some(index)) => vec.get(index),
+            (Some(serde_json::Value::Object(map)), _) => map.get(&token),
+            _ => None,
+        };
+        let quoted = serde_json::Value::String(token.clone()).to_string();
+        match (style, index) {
+            (_, Some(index)) => ret.push_str(&format!("[{}]", index)),
+            (PathStyle::Dotted, None) if ret.is_empty() && is_identifier(&token) => {
+                ret.push_str(&token)
+            }
+            (_, None) if is_identifier(&token) => ret.push_str(&format!(".{}", token)),
+            (PathStyle::Jq, None) => ret.push_str(&format!(".{}", quoted)),
+            (PathStyle::Jsonpath, None) => {
+                let escaped = token.replace('\\', "\\\\").replace('\'', "\\'");
+                ret.push_str(&format!("['{}']", escaped))
+            }
+            (_, None) => ret.push_str(&format!("[{}]", quoted)),
+        }
+    }
+    if ret.is_empty() {
+        ret.push('.');
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::path::{
+        escape, is_pointer, located, matches, outside_embedded, render, tokens, PathStyle,
+    };
+
+    #[test]
+    fn test_escape() {
+        assert_eq!("a~1b~0c", escape("a/b~c"));
+        assert_eq!(vec!["a/b~c", "~1"], tokens("/a~1b~0c/~01"));
+        assert!(tokens("/").is_empty());
+    }
+
+    #[test]
+    fn test_is_pointer() {
+        for pointer in ["", "/", "/a~0b/~1", "/0/-"] {
+            assert!(is_pointer(pointer), "{}", pointer);
+        }
+        for pointer in ["a", "/a~", "/a~2", "/body/~j/id"] {
+            assert!(!is_pointer(pointer), "{}", pointer);
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let document = serde_json::json!({
+            "spec": {"containers": [{"image": "nginx"}]},
+            "0": {"a.b": {"it's": true}},
+            "": []
+        });
+        let cases = [
+            (
+                "/spec/containers/0/image",
+                [
+                    "/spec/containers/0/image",
+                    ".spec.containers[0].image",
+                    "$.spec.containers[0].image",
+                    "spec.containers[0].image",
+                ],
+            ),
+            (
+                "/0/a.b/it's",
+                [
+                    "/0/a.b/it's",
+                    r#"."0"."a.b"."it's""#,
+                    r#"$['0']['a.b']['it\'s']"#,
+                    r#"["0"]["a.b"]["it's"]"#,
+                ],
+            ),
+            ("/", ["/", ".", "$", "."]),
+            ("//0", ["//0", r#".""[0]"#, "$[''][0]", r#"[""][0]"#]),
+        ];
+        for (pointer, expected) in cases {
+            let styles = [
+                PathStyle::Pointer,
+                PathStyle::Jq,
+                PathStyle::Jsonpath,
+                PathStyle::Dotted,
+            ];
+            for (style, expected) in styles.into_iter().zip(expected) {
+                assert_eq!(expected, render(pointer, style, &document));
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_unknown() {
+        let pointer = "/items/0/01";
+        assert_eq!(
+            ".items[0].\"01\"",
+            render(pointer, PathStyle::Jq, &serde_json::Value::Null)
+        );
+    }
+
+    #[test]
+    fn test_embedded() {
+        let document = serde_json::json!({"body": "{\"items\": [{\"id\": 1}]}"});
+        let pointer = "/body/~j/items/0/id";
+        assert_eq!("/body", outside_embedded(pointer));
+        assert_eq!("/body", located(pointer));
+        assert_eq!("", located("/"));
+        let expected = [
+            "/body#/items/0/id",
+            ".body | fromjson | .items[0].id",
+            "$.body#$.items[0].id",
+            "body#items[0].id",
+        ];
+        let styles = [
+            PathStyle::Pointer,
+            PathStyle::Jq,
+            PathStyle::Jsonpath,
+            PathStyle::Dotted,
+        ];
+        for (style, expected) in styles.into_iter().zip(expected) {
+            assert_eq!(expected, render(pointer, style, &document));
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("/items/*/body", "/items/0/body"));
+        assert!(matches("/a~1b", "/a~1b"));
+        assert!(!matches("/items/*/body", "/items/0/body/x"));
+        assert!(!matches("/items/*", "/other/0"));
+        assert!(matches("", "/"));
+    }
+}
@@ -0,0 +1,417 @@
+// Rebases a patch made against one version of a document onto the changes made since, so that
+// it applies to the newer version. Each operation is transformed past each change, in the style of
+// operational transformation: indices move with the elements that were inserted or removed before
+// them, operations already made by the changes are dropped, and operations on values the changes
+// replaced or removed can't be rebased. Neither can later operations that depend on them, such as
+// those inside a value or an array they changed.
+
+use crate::apply::{apply_one, index};
+use crate::path::{escape, tokens};
+use crate::{equal, CompareOptions, JsonPatchOp, Patch};
+
+#[derive(Debug, PartialEq)]
+pub struct Conflict {
+    pub patch: Patch,
+    pub reason: &'static str,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Rebase {
+    pub patches: Vec<Patch>,
+    pub conflicts: Vec<Conflict>,
+}
+
+enum Transformed {
+    Keep(Patch),
+    // Already made by the change.
+    Drop,
+    Conflict(&'static str),
+}
+
+pub fn rebase(patches: &[Patch], onto: &[Patch]) -> Rebase {
+    // The changes are transformed too as each operation is rebased, so that they always apply
+    // after the operations rebased so far.
+    let mut onto: Vec<Option<Patch>> = onto.iter().cloned().map(Some).collect();
+    let mut ret = Rebase::default();
+    // Where the operations that couldn't be rebased made their changes.
+    let mut regions: Vec<Vec<String>> = Vec::new();
+    for patch in patches {
+        let path = tokens(&patch.path);
+        if regions.iter().any(|region| path.starts_with(region)) {
+            ret.conflicts.push(Conflict {
+                patch: patch.clone(),
+                reason: "depends on an operation that couldn't be rebased",
+            });
+            regions.push(region(patch));
+            continue;
+        }
+        let mut rebased = onto.clone();
+        let mut current = Some(patch.clone());
+        let mut conflict = None;
+        for change in rebased.iter_mut() {
+            let (Some(p), Some(q)) = (&current, change.as_ref()) else {
+                continue;
+            };
+            match transform(p, q) {
+                Transformed::Keep(transformed) => {
+                    *change = transform_change(q, p);
+                    current = Some(transformed);
+                }
+                Transformed::Drop => {
+                    *change = None;
+                    current = None;
+                }
+                Transformed::Conflict(reason) => {
+                    conflict = Some(reason);
+                    break;
+                }
+            }
+        }
+        match conflict {
+            Some(reason) => {
+                ret.conflicts.push(Conflict {
+                    patch: patch.clone(),
+                    reason,
+                });
+                regions.push(region(patch));
+            }
+            None => {
+                onto = rebased;
+                ret.patches.extend(current);
+            }
+        }
+    }
+    ret
+}
+
+// The changes from `old` to `new`, for rebasing onto. Unlike `compare`, which compares arrays
+// index by index, elements inserted into or removed from the middle of an array are found from
+// the elements before and after them that didn't change, so that indices can move past them.
+// Elements in between those are compared pairwise.
+pub fn changes(old: &serde_json::Value, new: &serde_json::Value) -> Vec<Patch> {
+    let mut ret: Vec<Patch> = Vec::new();
+    diff(old, new, "", &mut ret);
+    ret
+}
+
+fn diff(old: &serde_json::Value, new: &serde_json::Value, path: &str, patches: &mut Vec<Patch>) {
+    let options = CompareOptions::default();
+    match (old, new) {
+        (serde_json::Value::Object(left), serde_json::Value::Object(right)) => {
+            for (key, old) in left {
+                let path = format!("{}/{}", path, escape(key));
+                match right.get(key) {
+                    Some(new) => diff(old, new, &path, patches),
+                    None => patches.push(change(JsonPatchOp::Remove, path, None, Some(old))),
+                }
+            }
+            for (key, new) in right.iter().filter(|(key, _)| !left.contains_key(*key)) {
+                let path = format!("{}/{}", path, escape(key));
+                patches.push(change(JsonPatchOp::Add, path, Some(new), None));
+            }
+        }
+        (serde_json::Value::Array(left), serde_json::Value::Array(right)) => {
+            let prefix = left
+                .iter()
+                .zip(right)
+                .take_while(|(old, new)| equal(old, new, &options))
+                .count();
+            let suffix = left[prefix..]
+                .iter()
+                .rev()
+                .zip(right[prefix..].iter().rev())
+                .take_while(|(old, new)| equal(old, new, &options))
+                .count();
+            let left = &left[prefix..left.len() - suffix];
+            let right = &right[prefix..right.len() - suffix];
+            let paired = left.len().min(right.len());
+            for i in 0..paired {
+                diff(
+                    &left[i],
+                    &right[i],
+                    &format!("{}/{}", path, prefix + i),
+                    patches,
+                );
+            }
+            // From the end, so that each index is still valid when the removals are applied in
+            // order.
+            for i in (paired..left.len()).rev() {
+                let path = format!("{}/{}", path, prefix + i);
+                patches.push(change(JsonPatchOp::Remove, path, None, Some(&left[i])));
+            }
+            for (i, new) in right.iter().enumerate().skip(paired) {
+                let path = format!("{}/{}", path, prefix + i);
+                patches.push(change(JsonPatchOp::Add, path, Some(new), None));
+            }
+        }
+        _ if !equal(old, new, &options) => {
+            // `compare` reports a change to the whole document at "/".
+            let path = if path.is_empty() { "/" } else { path }.to_string();
+            patches.push(change(JsonPatchOp::Replace, path, Some(new), Some(old)));
+        }
+        _ => (),
+    }
+}
+
+fn change(
+    op: JsonPatchOp,
+    path: String,
+    value: Option<&serde_json::Value>,
+    old_value: Option<&serde_json::Value>,
+) -> Patch {
+    Patch {
+        op,
+        path,
+        value: value.cloned(),
+        old_value: old_value.cloned(),
+    }
+}
+
+// Adding or removing an array element affects the whole array.
+fn region(patch: &Patch) -> Vec<String> {
+    let mut ret = tokens(&patch.path);
+    if shifts(patch).is_some() {
+        ret.pop();
+    }
+    ret
+}
+
+// The index an operation inserts or removes an array element at, if it does.
+fn shifts(patch: &Patch) -> Option<usize> {
+    match patch.op {
+        JsonPatchOp::Add | JsonPatchOp::Remove => tokens(&patch.path).last().and_then(|t| index(t)),
+        _ => None,
+    }
+}
+
+fn pointer(tokens: &[String]) -> String {
+    if tokens.is_empty() {
+        return "/".to_string();
+    }
+    tokens
+        .iter()
+        .map(|token| format!("/{}", escape(token)))
+        .collect()
+}
+
+// Where `path` goes through an element of the array that `shift` inserts or removes an element
+// of, the position of that element in `path`, and its index.
+fn element(path: &[String], shift: &[String]) -> Option<(usize, usize)> {
+    let container = shift.len() - 1;
+    if path.len() > container && path[..container] == shift[..container] {
+        index(&path[container]).map(|i| (container, i))
+    } else {
+        None
+    }
+}
+
+// `patch`, made after `change` instead of before it.
+fn transform(patch: &Patch, change: &Patch) -> Transformed {
+    let mut path = tokens(&patch.path);
+    let changed = tokens(&change.path);
+    if let Some(at) = shifts(change) {
+        if let Some((position, i)) = element(&path, &changed) {
+            let exact = path.len() == changed.len();
+            match change.op {
+                // An element inserted at the same index goes after the change's.
+                JsonPatchOp::Add if i >= at => path[position] = (i + 1).to_string(),
+                JsonPatchOp::Remove if i > at => path[position] = (i - 1).to_string(),
+                JsonPatchOp::Remove if i == at => {
+                    return match patch.op {
+                        JsonPatchOp::Add if exact => Transformed::Keep(patch.clone()),
+                        JsonPatchOp::Remove if exact => Transformed::Drop,
+                        _ => Transformed::Conflict("the element was removed"),
+                    };
+                }
+                _ => (),
+            }
+            return Transformed::Keep(Patch {
+                path: pointer(&path),
+                ..patch.clone()
+            });
+        }
+    }
+    if path == changed {
+        let same = patch.op == change.op
+            && match (&patch.value, &change.value) {
+                (Some(value), Some(changed)) => equal(value, changed, &CompareOptions::default()),
+                (None, None) => true,
+                _ => false,
+            };
+        return if same {
+            Transformed::Drop
+        } else {
+            Transformed::Conflict("the value was changed")
+        };
+    }
+    if path.starts_with(&changed) {
+        return Transformed::Conflict("a value it is in was changed");
+    }
+    if changed.starts_with(&path) {
+        return match patch.op {
+            // Removing the value removes the change too, so the old value includes it.
+            JsonPatchOp::Remove => {
+                let old_value = patch.old_value.clone().and_then(|mut old_value| {
+                    let relative = Patch {
+                        path: pointer(&changed[path.len()..]),
+                        ..change.clone()
+                    };
+                    apply_one(&mut old_value, &relative).ok()?;
+                    Some(old_value)
+                });
+                Transformed::Keep(Patch {
+                    old_value,
+                    ..patch.clone()
+                })
+            }
+            JsonPatchOp::Add if shifts(patch).is_some() => Transformed::Keep(patch.clone()),
+            _ => Transformed::Conflict("a value in it was changed"),
+        };
+    }
+    Transformed::Keep(patch.clone())
+}
+
+// `change`, made after `patch` (which `transform` kept) instead of before it. None where the
+// patch replaced or removed what it changed.
+fn transform_change(change: &Patch, patch: &Patch) -> Option<Patch> {
+    let mut path = tokens(&change.path);
+    let changed = tokens(&patch.path);
+    if let Some(at) = shifts(patch) {
+        if let Some((position, i)) = element(&path, &changed) {
+            let exact = path.len() == changed.len();
+            match patch.op {
+                // The change's element goes first, as in `transform`.
+                JsonPatchOp::Add
+                    if i > at || (i == at && !(exact && change.op == JsonPatchOp::Add)) =>
+                {
+                    path[position] = (i + 1).to_string()
+                }
+                JsonPatchOp::Remove if i > at => path[position] = (i - 1).to_string(),
+                JsonPatchOp::Remove if i == at && !(exact && change.op == JsonPatchOp::Add) => {
+                    return None
+                }
+                _ => (),
+            }
+            return Some(Patch {
+                path: pointer(&path),
+                ..change.clone()
+            });
+        }
+    }
+    if path.starts_with(&changed) {
+        return None;
+    }
+    Some(change.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::apply::apply;
+    use crate::rebase::{changes, rebase};
+    use crate::{compare, parse_json_patch, Patch};
+
+    fn patches(json_patch: serde_json::Value) -> Vec<Patch> {
+        parse_json_patch(&json_patch).unwrap()
+    }
+
+    #[test]
+    fn test_rebase() {
+        let old = serde_json::json!({"a": 1, "b": {"c": 2}, "l": [1, 2, 3, 4]});
+        // Inserts before the elements the patch changes, and makes one of its changes.
+        let onto = patches(serde_json::json!([
+            {"op": "add", "path": "/l/0", "value": 0},
+            {"op": "remove", "path": "/l/2"},
+            {"op": "replace", "path": "/a", "value": 5}
+        ]));
+        let patch = patches(serde_json::json!([
+            {"op": "replace", "path": "/l/3", "value": 40},
+            {"op": "add", "path": "/l/1", "value": 10},
+            {"op": "replace", "path": "/a", "value": 5},
+            {"op": "add", "path": "/b/d", "value": 3}
+        ]));
+        let rebase = rebase(&patch, &onto);
+        assert!(rebase.conflicts.is_empty());
+        let expected = patches(serde_json::json!([
+            {"op": "replace", "path": "/l/3", "value": 40},
+            {"op": "add", "path": "/l/2", "value": 10},
+            {"op": "add", "path": "/b/d", "value": 3}
+        ]));
+        assert_eq!(expected, rebase.patches);
+        let mut new = old.clone();
+        apply(&mut new, &onto).unwrap();
+        apply(&mut new, &rebase.patches).unwrap();
+        assert_eq!(
+            serde_json::json!({"a": 5, "b": {"c": 2, "d": 3}, "l": [0, 1, 10, 3, 40]}),
+            new
+        );
+    }
+
+    #[test]
+    fn test_rebase_onto_changes() {
+        // The upstream change inserts before the element the patch changes.
+        let old = serde_json::json!({"l": [{"a": 1}, {"a": 2}, {"a": 3}], "x": 1});
+        let new = serde_json::json!({"l": [{"a": 0}, {"a": 1}, {"a": 2}, {"a": 3}], "y": 2});
+        let onto = changes(&old, &new);
+        let expected = patches(serde_json::json!([
+            {"op": "add", "path": "/l/0", "value": {"a": 0}},
+            {"op": "remove", "path": "/x", "old_value": 1},
+            {"op": "add", "path": "/y", "value": 2}
+        ]));
+        assert_eq!(expected, onto);
+        let patch = patches(serde_json::json!([
+            {"op": "replace", "path": "/l/1/a", "value": 20}
+        ]));
+        let rebase = rebase(&patch, &onto);
+        assert!(rebase.conflicts.is_empty());
+        let mut rebased = new.clone();
+        apply(&mut rebased, &rebase.patches).unwrap();
+        assert_eq!(
+            serde_json::json!({"l": [{"a": 0}, {"a": 1}, {"a": 20}, {"a": 3}], "y": 2}),
+            rebased
+        );
+        // Elements that changed in place are compared inside.
+        let onto = changes(
+            &serde_json::json!([1, {"a": 1}]),
+            &serde_json::json!([1, {"a": 2}]),
+        );
+        let expected = patches(serde_json::json!([
+            {"op": "replace", "path": "/1/a", "value": 2, "old_value": 1}
+        ]));
+        assert_eq!(expected, onto);
+        // A change to the whole document is at "/", as `compare` reports it.
+        let onto = changes(&serde_json::json!(1), &serde_json::json!(2));
+        assert_eq!("/", onto[0].path);
+    }
+
+    #[test]
+    fn test_rebase_conflicts() {
+        let old = serde_json::json!({"a": {"b": 1}, "c": 2, "l": [1, 2, 3]});
+        let new = serde_json::json!({"a": 3, "c": 4, "l": [1, 3]});
+        let mut onto: Vec<Patch> = Vec::new();
+        compare(&old, &new, &mut onto);
+        let patch = patches(serde_json::json!([
+            {"op": "replace", "path": "/a/b", "value": 5},
+            {"op": "replace", "path": "/c", "value": 7},
+            {"op": "remove", "path": "/l/2"},
+            {"op": "remove", "path": "/l/1"},
+            {"op": "replace", "path": "/l/0", "value": 9},
+            {"op": "add", "path": "/e", "value": 8}
+        ]));
+        let rebase = rebase(&patch, &onto);
+        let reasons: Vec<(&str, &str)> = rebase
+            .conflicts
+            .iter()
+            .map(|conflict| (conflict.patch.path.as_str(), conflict.reason))
+            .collect();
+        let expected = vec![
+            ("/a/b", "a value it is in was changed"),
+            ("/c", "the value was changed"),
+            ("/l/1", "the value was changed"),
+            ("/l/0", "depends on an operation that couldn't be rebased"),
+        ];
+        assert_eq!(expected, reasons);
+        // The last element was removed by both.
+        let expected = patches(serde_json::json!([{"op": "add", "path": "/e", "value": 8}]));
+        assert_eq!(expected, rebase.patches);
+    }
+}
@@ -0,0 +1,222 @@
+// Summarizes a list of patches, for when the shape of a large change matters more than each
+// individual operation.
+
+use crate::format::Source;
+use crate::path;
+use crate::{JsonPatchOp, Patch};
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum Group {
+    /// Group changes by the top-level key they are under
+    Key,
+    /// Group changes by how deeply nested they are
+    Depth,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Counts {
+    pub add: usize,
+    pub remove: usize,
+    pub replace: usize,
+    // The size of the serialized values that were added, removed or replaced.
+    pub bytes: usize,
+}
+
+impl Counts {
+    fn count(&mut self, patch: &Patch) {
+        match patch.op {
+            JsonPatchOp::Add => self.add += 1,
+            JsonPatchOp::Remove => self.remove += 1,
+            JsonPatchOp::Replace => self.replace += 1,
+            JsonPatchOp::Reorder => return,
+        }
+        for value in [&patch.value, &patch.old_value].into_iter().flatten() {
+            self.bytes += value.to_string().len();
+        }
+    }
+
+    fn json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "add": self.add,
+            "remove": self.remove,
+            "replace": self.replace,
+            "bytes": self.bytes,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Stat {
+    pub group: Group,
+    // Keys are in the order they were first changed, depths in ascending order.
+    pub groups: Vec<(String, Counts)>,
+    pub total: Counts,
+}
+
+// Keys are written in the path style of the sources, and looked up in the one the change is in.
+pub fn stat(patches: &[Patch], group: Group, left: &Source, right: &Source) -> Stat {
+    let mut groups: Vec<(String, Counts)> = Vec::new();
+    let mut indices: HashMap<String, usize> = HashMap::new();
+    let mut total = Counts::default();
+    for patch in patches.iter().filter(|p| p.op != JsonPatchOp::Reorder) {
+        // `compare` reports a change to the whole document at "/".
+        let segments: Vec<&str> = if patch.path == "/" {
+            Vec::new()
+        } else {
+            patch.path.split('/').skip(1).collect()
+        };
+        let name = match group {
+            Group::Key => {
+                let source = match patch.op {
+                    JsonPatchOp::Add => right,
+                    _ => left,
+                };
+                let pointer = format!("/{}", segments.first().unwrap_or(&""));
+                path::render(&pointer, source.path_style, source.value)
+            }
+            Group::Depth => segments.len().to_string(),
+        };
+        let index = *indices.entry(name.clone()).or_insert_with(|| {
+            groups.push((name, Counts::default()));
+            groups.len() - 1
+        });
+        groups[index].1.count(patch);
+        total.count(patch);
+    }
+    if group == Group::Depth {
+        groups.sort_by_key(|(name, _)| name.parse::<usize>().unwrap());
+    }
+    Stat {
+        group,
+        groups,
+        total,
+    }
+}
+
+impl Stat {
+    fn header(&self) -> &'static str {
+        match self.group {
+            Group::Key => "key",
+            Group::Depth => "depth",
+        }
+    }
+
+    pub fn text(&self) -> String {
+        let mut rows: Vec<[String; 5]> = vec![[
+            self.header().to_string(),
+            "add".to_string(),
+            "remove".to_string(),
+            "replace".to_string(),
+            "bytes".to_string(),
+        ]];
+        let groups = self
+            .groups
+            .iter()
+            .map(|(name, counts)| (name.as_str(), counts));
+        for (name, counts) in groups.chain([("total", &self.total)]) {
+            rows.push([
+                name.to_string(),
+                counts.add.to_string(),
+                counts.remove.to_string(),
+                counts.replace.to_string(),
+                counts.bytes.to_string(),
+            ]);
+        }
+        let widths: Vec<usize> = (0..5)
+            .map(|i| rows.iter().map(|row| row[i].chars().count()).max().unwrap())
+            .collect();
+        let mut ret = String::new();
+        for row in rows {
+            // The first column is left aligned, the counts are right aligned.
+            ret.push_str(&format!("{:<width$}", row[0], width = widths[0]));
+            for (cell, width) in row.iter().zip(&widths).skip(1) {
+                ret.push_str(&format!("  {:>width$}", cell, width = width));
+            }
+            ret.push('\n');
+        }
+        ret
+    }
+
+    pub fn json(&self) -> serde_json::Value {
+        let groups: Vec<serde_json::Value> = self
+            .groups
+            .iter()
+            .map(|(name, counts)| {
+                let mut group = counts.json();
+                group[self.header()] = match self.group {
+                    Group::Key => serde_json::json!(name),
+                    Group::Depth => serde_json::json!(name.parse::<usize>().unwrap()),
+                };
+                group
+            })
+            .collect();
+        serde_json::json!({"by": self.header(), "groups": groups, "total": self.total.json()})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::{test_source, Source};
+    use crate::parser::Locations;
+    use crate::path::PathStyle;
+    use crate::stat::{stat, Group, Stat};
+    use crate::{compare, Patch};
+
+    fn stat_of(group: Group, path_style: PathStyle) -> Stat {
+        let left = serde_json::json!({
+            "spec": {"image": "nginx:1.0", "ports": [80, 443]},
+            "status": "ok",
+            "removed": true
+        });
+        let right = serde_json::json!({
+            "spec": {"image": "nginx:1.1", "ports": [80], "replicas": 3},
+            "status": "degraded"
+        });
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(&left, &right, &mut patches);
+        let locations = Locations::new();
+        let source = |name, value| Source {
+            path_style,
+            ..test_source(name, value, &locations)
+        };
+        stat(
+            &patches,
+            group,
+            &source("left.json", &left),
+            &source("right.json", &right),
+        )
+    }
+
+    #[test]
+    fn test_stat_by_key() {
+        let expected = "\
+key       add  remove  replace  bytes
+/spec       1       1        1     26
+/status     0       0        1     14
+/removed    0       1        0      4
+total       1       2        2     44
+";
+        assert_eq!(expected, stat_of(Group::Key, PathStyle::Pointer).text());
+        let keys: Vec<String> = stat_of(Group::Key, PathStyle::Jq)
+            .groups
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(vec![".spec", ".status", ".removed"], keys);
+    }
+
+    #[test]
+    fn test_stat_by_depth() {
+        let expected = serde_json::json!({
+            "by": "depth",
+            "groups": [
+                {"depth": 1, "add": 0, "remove": 1, "replace": 1, "bytes": 18},
+                {"depth": 2, "add": 1, "remove": 0, "replace": 1, "bytes": 23},
+                {"depth": 3, "add": 0, "remove": 1, "replace": 0, "bytes": 3},
+            ],
+            "total": {"add": 1, "remove": 2, "replace": 2, "bytes": 44}
+        });
+        assert_eq!(expected, stat_of(Group::Depth, PathStyle::Pointer).json());
+    }
+}
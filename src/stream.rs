@@ -0,0 +1,439 @@
+// Compares two documents while reading them, for files too large to hold in memory. Both are
+// walked in lockstep, and a value is only built where the documents diverge: a changed scalar,
+// a value that changed type, the tail of a longer array, or an object member whose key isn't
+// where it is on the other side. Patches are emitted as soon as they are found, so
+// mostly-identical files are compared in memory proportional to their nesting and their
+// differences rather than their size.
+
+use crate::hash::Hashes;
+use crate::parser::{self, DuplicateKey, Parser, Start};
+use crate::{compare_values, equal, CompareOptions, JsonPatchOp, Patch};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::BufRead;
+
+#[derive(Debug)]
+pub enum Error {
+    Left(parser::Error),
+    Right(parser::Error),
+}
+
+struct Walker<'a, L: BufRead, R: BufRead, F: FnMut(Patch)> {
+    left: Parser<L>,
+    right: Parser<R>,
+    options: &'a CompareOptions,
+    emit: F,
+    // Patches of object members that must wait for the members before them to be reported.
+    held: Vec<Vec<Patch>>,
+}
+
+// The members of an object in the order of the left document, once its keys have differed: the
+// patches of each, or its left value while its key hasn't been found on the right. Patches are
+// reported in this order, as `compare` does, so each waits for the members before it.
+#[derive(Default)]
+struct Members {
+    slots: VecDeque<Slot>,
+    // How many slots have been reported, so that `waiting` can index the rest.
+    reported: usize,
+    waiting: HashMap<String, usize>,
+}
+
+enum Slot {
+    Done(Vec<Patch>),
+    Waiting(String, serde_json::Value),
+}
+
+impl Members {
+    fn wait(&mut self, key: String, old: serde_json::Value) {
+        self.waiting
+            .insert(key.clone(), self.reported + self.slots.len());
+        self.slots.push_back(Slot::Waiting(key, old));
+    }
+
+    fn find(&mut self, key: &str) -> Option<&mut Slot> {
+        let index = self.waiting.remove(key)?;
+        self.slots.get_mut(index - self.reported)
+    }
+
+    fn remove_waiting(&mut self, path: &str, options: &CompareOptions) {
+        for slot in &mut self.slots {
+            if let Slot::Waiting(key, old) = slot {
+                let mut patches: Vec<Patch> = Vec::new();
+                if !options.like_missing(old) {
+                    patches.push(Patch {
+                        op: JsonPatchOp::Remove,
+                        path: format!("{}/{}", path, crate::path::escape(key)),
+                        value: None,
+                        old_value: Some(std::mem::take(old)),
+                    });
+                }
+                *slot = Slot::Done(patches);
+            }
+        }
+        self.waiting.clear();
+    }
+}
+
+// The next key of an object, noting it if the object already had it.
+fn next_key<R: BufRead>(
+    parser: &mut Parser<R>,
+    first: bool,
+    path: &mut String,
+    keys: &mut HashSet<String>,
+) -> Result<Option<String>, parser::Error> {
+    let Some((key, position)) = parser.next_key(first)? else {
+        return Ok(None);
+    };
+    if !keys.insert(key.clone()) {
+        let len = push_key(path, &key);
+        parser.duplicate(path, position);
+        path.truncate(len);
+    }
+    Ok(Some(key))
+}
+
+// Appends a key to a path, returning the length to truncate it back to.
+fn push_key(path: &mut String, key: &str) -> usize {
+    let len = path.len();
+    path.push('/');
+    path.push_str(&crate::path::escape(key));
+    len
+}
+
+// Returns the duplicate keys found in each document. The patches for an object with duplicate
+// keys may differ from `compare`'s, in which the last value wins.
+pub fn compare<L: BufRead, R: BufRead>(
+    left: L,
+    right: R,
+    options: &CompareOptions,
+    emit: impl FnMut(Patch),
+) -> Result<(Vec<DuplicateKey>, Vec<DuplicateKey>), Error> {
+    let mut walker = Walker {
+        left: Parser::new(left, false),
+        right: Parser::new(right, false),
+        options,
+        emit,
+        held: Vec::new(),
+    };
+    walker.value(&mut String::new(), 0, false)?;
+    walker.left.end().map_err(Error::Left)?;
+    walker.right.end().map_err(Error::Right)?;
+    Ok((
+        walker.left.into_duplicates(),
+        walker.right.into_duplicates(),
+    ))
+}
+
+impl<L: BufRead, R: BufRead, F: FnMut(Patch)> Walker<'_, L, R, F> {
+    fn report(&mut self, patch: Patch) {
+        match self.held.last_mut() {
+            Some(held) => held.push(patch),
+            None => (self.emit)(patch),
+        }
+    }
+
+    // `member` is whether the value is an object member, which, like `compare_objects`, is
+    // skipped if both sides are like missing.
+    fn value(&mut self, path: &mut String, depth: usize, member: bool) -> Result<(), Error> {
+        let left = self.left.start(path, depth).map_err(Error::Left)?;
+        let right = self.right.start(path, depth).map_err(Error::Right)?;
+        match (left, right) {
+            (Start::Object, Start::Object) => self.object(path, depth),
+            (Start::Array, Start::Array) => self.array(path, depth),
+            (left, right) => {
+                let old = self.left.finish(left, path, depth).map_err(Error::Left)?;
+                let new = self
+                    .right
+                    .finish(right, path, depth)
+                    .map_err(Error::Right)?;
+                let missing =
+                    member && self.options.like_missing(&old) && self.options.like_missing(&new);
+                if !missing && !equal(&old, &new, self.options) {
+                    // `compare` reports a change to the whole document at "/".
+                    self.report(Patch {
+                        op: JsonPatchOp::Replace,
+                        path: if path.is_empty() { "/" } else { path }.to_string(),
+                        value: Some(new),
+                        old_value: Some(old),
+                    });
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn object(&mut self, path: &mut String, depth: usize) -> Result<(), Error> {
+        let (mut left_keys, mut right_keys) = (HashSet::new(), HashSet::new());
+        let mut left = next_key(&mut self.left, true, path, &mut left_keys).map_err(Error::Left)?;
+        let mut right =
+            next_key(&mut self.right, true, path, &mut right_keys).map_err(Error::Right)?;
+        let mut members = Members::default();
+        // Members only on the right so far, in their order on the right.
+        let mut added = serde_json::Map::new();
+        // Where the keys differ and neither is already waiting, the sides take turns being read
+        // ahead, so that an inserted or a removed key only costs a few buffered members.
+        let mut take_left = true;
+        loop {
+            match (&left, &right) {
+                (None, None) => break,
+                (Some(key), Some(right_key)) if key == right_key => {
+                    let len = push_key(path, key);
+                    if members.slots.is_empty() {
+                        self.value(path, depth + 1, true)?;
+                    } else {
+                        self.held.push(Vec::new());
+                        let result = self.value(path, depth + 1, true);
+                        let patches = self.held.pop().unwrap_or_default();
+                        result?;
+                        members.slots.push_back(Slot::Done(patches));
+                    }
+                    path.truncate(len);
+                    left = next_key(&mut self.left, false, path, &mut left_keys)
+                        .map_err(Error::Left)?;
+                    right = next_key(&mut self.right, false, path, &mut right_keys)
+                        .map_err(Error::Right)?;
+                }
+                (left_key, right_key) => {
+                    take_left = match (left_key, right_key) {
+                        (Some(key), _) if added.contains_key(key) => true,
+                        (_, Some(key)) if members.waiting.contains_key(key) => false,
+                        (Some(_), None) => true,
+                        (None, Some(_)) => false,
+                        _ => !take_left,
+                    };
+                    if take_left {
+                        let key = left.take().unwrap_or_default();
+                        let len = push_key(path, &key);
+                        let old = self
+                            .left
+                            .parse_value(path, depth + 1)
+                            .map_err(Error::Left)?;
+                        match added.shift_remove(&key) {
+                            Some(new) => {
+                                let patches = self.compare_apart(&old, &new, path);
+                                members.slots.push_back(Slot::Done(patches));
+                            }
+                            None => members.wait(key, old),
+                        }
+                        path.truncate(len);
+                        left = next_key(&mut self.left, false, path, &mut left_keys)
+                            .map_err(Error::Left)?;
+                    } else {
+                        let key = right.take().unwrap_or_default();
+                        let len = push_key(path, &key);
+                        let new = self
+                            .right
+                            .parse_value(path, depth + 1)
+                            .map_err(Error::Right)?;
+                        match members.find(&key) {
+                            Some(slot) => {
+                                if let Slot::Waiting(_, old) = slot {
+                                    *slot = Slot::Done(self.compare_apart(old, &new, path));
+                                }
+                            }
+                            None => {
+                                added.insert(key, new);
+                            }
+                        }
+                        path.truncate(len);
+                        right = next_key(&mut self.right, false, path, &mut right_keys)
+                            .map_err(Error::Right)?;
+                    }
+                }
+            }
+            // Once the right object has ended, the keys still waiting were removed.
+            if right.is_none() {
+                members.remove_waiting(path, self.options);
+            }
+            while let Some(Slot::Done(_)) = members.slots.front() {
+                if let Some(Slot::Done(patches)) = members.slots.pop_front() {
+                    patches.into_iter().for_each(|patch| self.report(patch));
+                }
+                members.reported += 1;
+            }
+            // Once the left object has ended and everything before has been reported, what is
+            // only on the right was added.
+            if left.is_none() && members.slots.is_empty() {
+                for (key, new) in std::mem::take(&mut added) {
+                    if !self.options.like_missing(&new) {
+                        self.report(Patch {
+                            op: JsonPatchOp::Add,
+                            path: format!("{}/{}", path, crate::path::escape(&key)),
+                            value: Some(new),
+                            old_value: None,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Two members with the same key that weren't read side by side, compared in memory as
+    // `compare_objects` does.
+    fn compare_apart(
+        &self,
+        old: &serde_json::Value,
+        new: &serde_json::Value,
+        path: &str,
+    ) -> Vec<Patch> {
+        let mut patches: Vec<Patch> = Vec::new();
+        if self.options.like_missing(old) && self.options.like_missing(new) {
+            return patches;
+        }
+        let hashes = Hashes::new(old, new, self.options);
+        if !hashes.equal(old, new, self.options) {
+            compare_values(old, new, &mut patches, path, self.options, &hashes);
+        }
+        patches
+    }
+
+    fn array(&mut self, path: &mut String, depth: usize) -> Result<(), Error> {
+        let mut left = self.left.next_element(true).map_err(Error::Left)?;
+        let mut right = self.right.next_element(true).map_err(Error::Right)?;
+        let mut i = 0;
+        // Removals are emitted from the end once the left array has ended, as `compare` does.
+        let mut removed: Vec<Patch> = Vec::new();
+        while left || right {
+            let len = path.len();
+            path.push_str(&format!("/{}", i));
+            if left && right {
+                self.value(path, depth + 1, false)?;
+            } else if left {
+                let old = self
+                    .left
+                    .parse_value(path, depth + 1)
+                    .map_err(Error::Left)?;
+                removed.push(Patch {
+                    op: JsonPatchOp::Remove,
+                    path: path.clone(),
+                    value: None,
+                    old_value: Some(old),
+                });
+            } else {
+                let new = self
+                    .right
+                    .parse_value(path, depth + 1)
+                    .map_err(Error::Right)?;
+                self.report(Patch {
+                    op: JsonPatchOp::Add,
+                    path: path.clone(),
+                    value: Some(new),
+                    old_value: None,
+                });
+            }
+            path.truncate(len);
+            // An array that has ended must not be read any further.
+            if left {
+                left = self.left.next_element(false).map_err(Error::Left)?;
+            }
+            if right {
+                right = self.right.next_element(false).map_err(Error::Right)?;
+            }
+            i += 1;
+        }
+        for patch in removed.into_iter().rev() {
+            self.report(patch);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stream::{compare, Error};
+    use crate::{compare_with, CompareOptions, Patch};
+
+    fn stream(left: &str, right: &str) -> Result<Vec<Patch>, Error> {
+        stream_with(left, right, &CompareOptions::default())
+    }
+
+    fn stream_with(left: &str, right: &str, options: &CompareOptions) -> Result<Vec<Patch>, Error> {
+        let mut patches: Vec<Patch> = Vec::new();
+        compare(left.as_bytes(), right.as_bytes(), options, |patch| {
+            patches.push(patch)
+        })?;
+        Ok(patches)
+    }
+
+    #[test]
+    fn test_stream_matches_compare() {
+        let cases = [
+            (r#"{"a": 1}"#, r#"{"a": 1}"#),
+            (r#""a""#, "42"),
+            (r#"[1, 2, 3]"#, r#"[1, 4]"#),
+            (r#"[1, {"a": [2]}]"#, r#"[1, {"a": [2, 3]}, "b"]"#),
+            (
+                r#"{"a": 1, "b": {"c": 2, "d": [3]}, "e": 4}"#,
+                r#"{"a": 1.0, "b": {"c": 2, "d": {}}, "e": 5}"#,
+            ),
+            (
+                r#"{"a": 1, "b": 2, "c": {"x": 3}, "removed": 4}"#,
+                r#"{"a": 1, "c": {"x": 5}, "added": 6, "b": 2}"#,
+            ),
+            (r#"{"a/b": {"c~d": 1}}"#, r#"{"a/b": {"c~d": 2}}"#),
+            (r#"{"a": []}"#, r#"{}"#),
+            (r#"{"a": 1, "b": 2}"#, r#"{"x": 0, "a": 1, "b": 3}"#),
+            (r#"{"x": 0, "a": 1, "b": 2}"#, r#"{"a": 1, "b": 3}"#),
+            (r#"{"a": 1, "b": 2, "c": 3}"#, r#"{"c": 4, "b": 2, "a": 5}"#),
+            (
+                r#"{"a": 1, "r": 1, "b": {"x": 1}, "s": 2, "c": 3, "t": 4}"#,
+                r#"{"n": 1, "a": 2, "b": {"x": 2}, "o": [1], "c": 3, "p": {}}"#,
+            ),
+            (
+                r#"{"a": [], "b": null, "c": {}, "d": [null]}"#,
+                r#"{"a": {}, "b": [], "c": null, "d": [[]]}"#,
+            ),
+            (
+                r#"{"x": 0, "a": [], "b": null, "c": 1}"#,
+                r#"{"a": null, "b": {}, "y": [], "c": 1}"#,
+            ),
+            (r#"[null, []]"#, r#"[{}, null]"#),
+        ];
+        let like_missing = CompareOptions {
+            null_equals_missing: true,
+            empty_equals_missing: true,
+            ..Default::default()
+        };
+        for options in [CompareOptions::default(), like_missing] {
+            for (left, right) in cases {
+                let mut expected: Vec<Patch> = Vec::new();
+                compare_with(
+                    &serde_json::from_str(left).unwrap(),
+                    &serde_json::from_str(right).unwrap(),
+                    &mut expected,
+                    &options,
+                );
+                let patches = stream_with(left, right, &options).unwrap();
+                assert_eq!(expected, patches, "{} {}", left, right);
+            }
+        }
+    }
+
+    #[test]
+    fn test_stream_after_inserted_key() {
+        // The right document only fails to parse at its end, so the patches found before then
+        // show that the members after the inserted key were streamed rather than built.
+        let left = r#"{"a": 1, "b": {"c": 2}, "d": 3}"#;
+        let right = r#"{"new": 0, "a": 1, "b": {"c": 4}, "d": 3 x"#;
+        let mut paths: Vec<String> = Vec::new();
+        let result = compare(
+            left.as_bytes(),
+            right.as_bytes(),
+            &CompareOptions::default(),
+            |patch| paths.push(patch.path),
+        );
+        assert!(matches!(result, Err(Error::Right(_))));
+        assert_eq!(vec!["/b/c"], paths);
+    }
+
+    #[test]
+    fn test_stream_errors() {
+        match stream(r#"{"a": [1, 2]}"#, r#"{"a": [1 2]}"#) {
+            Err(Error::Right(error)) => {
+                assert_eq!("expected `,` or `]` at line 1 column 10", error.to_string())
+            }
+            result => panic!("unexpected {:?}", result),
+        }
+        assert!(matches!(stream("[1] [2]", "[1]"), Err(Error::Left(_))));
+    }
+}